@@ -0,0 +1,358 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    api::{apply_error, change_meta, if_match, tag_headers, AppState},
+    policy::{PiiConfig, PolicyFile, Rule, SemanticCase, SemanticConfig, SemanticExample},
+    store::PolicyEditError,
+};
+
+// -----------------------------
+// Rules (JSON, by id)
+// -----------------------------
+
+pub async fn list_rules(State(st): State<AppState>) -> impl IntoResponse {
+    let (policy, tag) = st.store.get_policy_tagged().await;
+    (tag_headers(&tag), Json(policy.rules))
+}
+
+pub async fn get_rule(State(st): State<AppState>, Path(id): Path<String>) -> Response {
+    let (policy, tag) = st.store.get_policy_tagged().await;
+    match policy.rules.into_iter().find(|r| r.id == id) {
+        Some(rule) => (tag_headers(&tag), Json(rule)).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("rule {id} not found")).into_response(),
+    }
+}
+
+pub async fn create_rule(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    Json(rule): Json<Rule>,
+) -> Response {
+    edit(&st, &headers, StatusCode::CREATED, |p| {
+        if p.rules.iter().any(|r| r.id == rule.id) {
+            return Err(PolicyEditError::Conflict(format!("rule {}", rule.id)).into());
+        }
+        p.rules.push(rule.clone());
+        Ok(rule.clone())
+    })
+    .await
+}
+
+pub async fn replace_rule(
+    State(st): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(rule): Json<Rule>,
+) -> Response {
+    edit(&st, &headers, StatusCode::OK, |p| {
+        let slot = find_rule(p, &id)?;
+        if rule.id != id {
+            return Err(PolicyEditError::Invalid("rule id cannot be changed".into()).into());
+        }
+        *slot = rule.clone();
+        Ok(rule.clone())
+    })
+    .await
+}
+
+/// JSON Merge Patch (RFC 7396) of one rule.
+pub async fn patch_rule(
+    State(st): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> Response {
+    edit(&st, &headers, StatusCode::OK, |p| {
+        let slot = find_rule(p, &id)?;
+        let rule: Rule = patched(&*slot, &patch)?;
+        if rule.id != id {
+            return Err(PolicyEditError::Invalid("rule id cannot be changed".into()).into());
+        }
+        *slot = rule.clone();
+        Ok(rule)
+    })
+    .await
+}
+
+pub async fn delete_rule(
+    State(st): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    edit(&st, &headers, StatusCode::OK, |p| {
+        let i = p
+            .rules
+            .iter()
+            .position(|r| r.id == id)
+            .ok_or_else(|| PolicyEditError::NotFound(format!("rule {id}")))?;
+        Ok(p.rules.remove(i))
+    })
+    .await
+}
+
+pub async fn enable_rule(
+    State(st): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    set_enabled(&st, &headers, &id, true).await
+}
+
+pub async fn disable_rule(
+    State(st): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    set_enabled(&st, &headers, &id, false).await
+}
+
+async fn set_enabled(st: &AppState, headers: &HeaderMap, id: &str, enabled: bool) -> Response {
+    edit(st, headers, StatusCode::OK, |p| {
+        let rule = find_rule(p, id)?;
+        rule.enabled = enabled;
+        Ok(rule.clone())
+    })
+    .await
+}
+
+fn find_rule<'a>(p: &'a mut PolicyFile, id: &str) -> anyhow::Result<&'a mut Rule> {
+    p.rules
+        .iter_mut()
+        .find(|r| r.id == id)
+        .ok_or_else(|| PolicyEditError::NotFound(format!("rule {id}")).into())
+}
+
+// -----------------------------
+// Sections (pii, semantic)
+// -----------------------------
+
+pub async fn get_pii(State(st): State<AppState>) -> impl IntoResponse {
+    let (policy, tag) = st.store.get_policy_tagged().await;
+    (tag_headers(&tag), Json(policy.pii))
+}
+
+pub async fn replace_pii(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    Json(pii): Json<PiiConfig>,
+) -> Response {
+    edit(&st, &headers, StatusCode::OK, |p| {
+        p.pii = pii.clone();
+        Ok(pii.clone())
+    })
+    .await
+}
+
+pub async fn patch_pii(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> Response {
+    edit(&st, &headers, StatusCode::OK, |p| {
+        p.pii = patched(&p.pii, &patch)?;
+        Ok(p.pii.clone())
+    })
+    .await
+}
+
+pub async fn get_semantic(State(st): State<AppState>) -> impl IntoResponse {
+    let (policy, tag) = st.store.get_policy_tagged().await;
+    (tag_headers(&tag), Json(policy.semantic))
+}
+
+pub async fn replace_semantic(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    Json(semantic): Json<SemanticConfig>,
+) -> Response {
+    edit(&st, &headers, StatusCode::OK, |p| {
+        p.semantic = semantic.clone();
+        Ok(semantic.clone())
+    })
+    .await
+}
+
+pub async fn patch_semantic(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> Response {
+    edit(&st, &headers, StatusCode::OK, |p| {
+        p.semantic = patched(&p.semantic, &patch)?;
+        Ok(p.semantic.clone())
+    })
+    .await
+}
+
+pub async fn create_semantic_case(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    Json(case): Json<SemanticCase>,
+) -> Response {
+    edit(&st, &headers, StatusCode::CREATED, |p| {
+        if p.semantic.cases.iter().any(|c| c.id == case.id) {
+            return Err(PolicyEditError::Conflict(format!("semantic case {}", case.id)).into());
+        }
+        p.semantic.cases.push(case.clone());
+        Ok(case.clone())
+    })
+    .await
+}
+
+pub async fn delete_semantic_case(
+    State(st): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    edit(&st, &headers, StatusCode::OK, |p| {
+        let i = p
+            .semantic
+            .cases
+            .iter()
+            .position(|c| c.id == id)
+            .ok_or_else(|| PolicyEditError::NotFound(format!("semantic case {id}")))?;
+        Ok(p.semantic.cases.remove(i))
+    })
+    .await
+}
+
+/// Appends an example (`"text"` or `{text, embedding}`); returns the case.
+pub async fn add_semantic_example(
+    State(st): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(example): Json<SemanticExample>,
+) -> Response {
+    edit(&st, &headers, StatusCode::CREATED, |p| {
+        let case = find_case(p, &id)?;
+        case.examples.push(example.clone());
+        Ok(case.clone())
+    })
+    .await
+}
+
+/// Removes the example at `index`; returns the case.
+pub async fn delete_semantic_example(
+    State(st): State<AppState>,
+    Path((id, index)): Path<(String, usize)>,
+    headers: HeaderMap,
+) -> Response {
+    edit(&st, &headers, StatusCode::OK, |p| {
+        let case = find_case(p, &id)?;
+        if index >= case.examples.len() {
+            return Err(PolicyEditError::NotFound(format!(
+                "example {index} of semantic case {id}"
+            ))
+            .into());
+        }
+        case.examples.remove(index);
+        Ok(case.clone())
+    })
+    .await
+}
+
+fn find_case<'a>(p: &'a mut PolicyFile, id: &str) -> anyhow::Result<&'a mut SemanticCase> {
+    p.semantic
+        .cases
+        .iter_mut()
+        .find(|c| c.id == id)
+        .ok_or_else(|| PolicyEditError::NotFound(format!("semantic case {id}")).into())
+}
+
+// -----------------------------
+// Helpers
+// -----------------------------
+
+/// Applies `f` to the active policy through `RuleStore::update_policy`
+/// (honoring `If-Match`) and returns what it produced, with the new
+/// policy's ETag and version.
+async fn edit<T: Serialize>(
+    st: &AppState,
+    headers: &HeaderMap,
+    status: StatusCode,
+    f: impl Fn(&mut PolicyFile) -> anyhow::Result<T>,
+) -> Response {
+    let expected = if_match(headers);
+    match st
+        .store
+        .update_policy(change_meta(headers), expected.as_deref(), f)
+        .await
+    {
+        Ok((out, v)) => (status, tag_headers(&v.tag()), Json(out)).into_response(),
+        Err(e) => apply_error(st, e).await,
+    }
+}
+
+/// `current` with a JSON Merge Patch applied.
+fn patched<T: Serialize + DeserializeOwned>(current: &T, patch: &Value) -> anyhow::Result<T> {
+    let mut value = serde_json::to_value(current)?;
+    merge_patch(&mut value, patch);
+    serde_json::from_value(value)
+        .map_err(|e| PolicyEditError::Invalid(format!("invalid patch: {e}")).into())
+}
+
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let Value::Object(target) = target else {
+        unreachable!()
+    };
+    for (k, v) in patch {
+        if v.is_null() {
+            target.remove(k);
+        } else {
+            merge_patch(target.entry(k.clone()).or_insert(Value::Null), v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Action;
+    use serde_json::json;
+
+    #[test]
+    fn merge_patch_follows_rfc7396() {
+        let mut doc = json!({"a": "b", "c": {"d": "e", "f": "g"}});
+        merge_patch(&mut doc, &json!({"a": "z", "c": {"f": null}, "h": [1]}));
+        assert_eq!(doc, json!({"a": "z", "c": {"d": "e"}, "h": [1]}));
+    }
+
+    #[test]
+    fn patched_rule_keeps_other_fields() {
+        let rule: Rule = serde_yaml::from_str(
+            r#"
+id: r
+applies_to: prompt
+action: block
+priority: 10
+when: {any: [{type: keywords, field: text, values: [foo]}]}
+"#,
+        )
+        .unwrap();
+
+        let out: Rule = patched(&rule, &json!({"action": "flag", "enabled": false})).unwrap();
+        assert_eq!(out.action, Action::Flag);
+        assert!(!out.enabled);
+        assert_eq!(out.priority, 10);
+
+        let err = patched::<Rule>(&rule, &json!({"priority": "high"})).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(PolicyEditError::Invalid(_))
+        ));
+    }
+}
@@ -1,23 +1,45 @@
 use axum::{
-    extract::State,
+    extract::{DefaultBodyLimit, Path, Query, State},
     http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post},
     Json, Router,
 };
+use std::{sync::Arc, time::Instant};
 use uuid::Uuid;
 
-use crate::compile::{CompiledMatch, CompiledRule};
 use crate::{
-    pii_regex::{PiiRegexDetector, PiiType},
-    policy::{Action, AppliesTo, EvalRequest, EvalResponse, Kind, PiiMode, PolicyFile},
-    store::RuleStore,
+    admin,
+    decode::PayloadDecoder,
+    dry_run::DryRunRequest,
+    evaluator::{
+        applies, evaluate_decoded, evaluate_stage1, redact_spans, trace_rules, EvalInput,
+        Stage1Outcome,
+    },
+    history::{ChangeMeta, VersionInfo},
+    pii_regex::{Finding, PiiRegexDetector, PiiType},
+    policy::{
+        Action, BatchError, BatchResult, DecodedFrom, DecodedTrace, EvalRequest, EvalResponse,
+        EvalTrace, ExplainResponse, Kind, PiiConfig, PiiEntity, PiiMode, PiiTrace, PolicyFile,
+        RuleStatus, SchemaViolation, SemanticScore, SemanticTrace, StageTiming,
+    },
+    proxy::{chat_completions, Upstream},
+    store::{InvalidPolicy, PolicyEditError, PolicySnapshot, PolicyTag, RuleStore, StalePolicy},
+    stream::eval_stream,
+    validate::{parse_policy, PolicyError, PolicyErrorKind, PolicyErrors},
 };
 
+/// Largest `/v1/eval/batch` request, in items and in bytes.
+const MAX_BATCH_ITEMS: usize = 10_000;
+const MAX_BATCH_BODY_BYTES: usize = 64 * 1024 * 1024;
+
 #[derive(Clone)]
 pub struct AppState {
     pub store: RuleStore,
     pub pii_regex: PiiRegexDetector,
+    pub decoder: PayloadDecoder,
+    /// Set in proxy mode (`PROXY_UPSTREAM_URL`).
+    pub upstream: Option<Upstream>,
 }
 
 pub fn router(state: AppState) -> Router {
@@ -25,8 +47,75 @@ pub fn router(state: AppState) -> Router {
         .route("/healthz", get(|| async { "ok" }))
         // Data plane
         .route("/v1/eval", post(eval))
+        .route("/v1/eval/explain", post(eval_explain))
+        .route(
+            "/v1/eval/batch",
+            post(eval_batch).layer(DefaultBodyLimit::max(MAX_BATCH_BODY_BYTES)),
+        )
+        // Incremental evaluation of streamed text (WebSocket)
+        .route("/v1/eval/stream", get(eval_stream))
+        // OpenAI-compatible proxy (evaluates both directions)
+        .route("/v1/chat/completions", post(chat_completions))
         // Control plane: policy is YAML source of truth
         .route("/admin/v1/policy", get(get_policy_yaml).post(apply_policy_yaml))
+        .route(
+            "/admin/v1/policy/dry-run",
+            post(dry_run_policy).layer(DefaultBodyLimit::max(MAX_BATCH_BODY_BYTES)),
+        )
+        .route(
+            "/admin/v1/policy/lint",
+            get(lint_active_policy).post(lint_policy_yaml),
+        )
+        // Shadow policy: evaluated on live /v1/eval traffic, never enforced
+        .route(
+            "/admin/v1/policy/shadow",
+            get(get_shadow_yaml)
+                .post(set_shadow_yaml)
+                .delete(clear_shadow),
+        )
+        .route("/admin/v1/policy/shadow/metrics", get(shadow_metrics))
+        .route("/admin/v1/policy/shadow/promote", post(promote_shadow))
+        // Control plane: individual rules and sections (JSON)
+        .route("/admin/v1/rules", get(admin::list_rules).post(admin::create_rule))
+        .route(
+            "/admin/v1/rules/:id",
+            get(admin::get_rule)
+                .put(admin::replace_rule)
+                .patch(admin::patch_rule)
+                .delete(admin::delete_rule),
+        )
+        .route("/admin/v1/rules/:id/enable", post(admin::enable_rule))
+        .route("/admin/v1/rules/:id/disable", post(admin::disable_rule))
+        .route(
+            "/admin/v1/pii",
+            get(admin::get_pii)
+                .put(admin::replace_pii)
+                .patch(admin::patch_pii),
+        )
+        .route(
+            "/admin/v1/semantic",
+            get(admin::get_semantic)
+                .put(admin::replace_semantic)
+                .patch(admin::patch_semantic),
+        )
+        .route("/admin/v1/semantic/cases", post(admin::create_semantic_case))
+        .route(
+            "/admin/v1/semantic/cases/:id",
+            delete(admin::delete_semantic_case),
+        )
+        .route(
+            "/admin/v1/semantic/cases/:id/examples",
+            post(admin::add_semantic_example),
+        )
+        .route(
+            "/admin/v1/semantic/cases/:id/examples/:index",
+            delete(admin::delete_semantic_example),
+        )
+        // Version history
+        .route("/admin/v1/policy/versions", get(list_versions))
+        .route("/admin/v1/policy/versions/:version", get(get_version_yaml))
+        .route("/admin/v1/policy/versions/:from/diff/:to", get(diff_versions))
+        .route("/admin/v1/policy/versions/:version/rollback", post(rollback_policy))
         .with_state(state)
         .layer(tower_http::trace::TraceLayer::new_for_http())
 }
@@ -36,9 +125,245 @@ pub fn router(state: AppState) -> Router {
 // -----------------------------
 
 async fn get_policy_yaml(State(st): State<AppState>) -> impl IntoResponse {
+    let (policy, tag) = st.store.get_policy_tagged().await;
+    policy_response(StatusCode::OK, &policy, &tag)
+}
+
+/// The policy as YAML, with its ETag (and version, if recorded).
+fn policy_response(
+    status: StatusCode,
+    policy: &PolicyFile,
+    tag: &PolicyTag,
+) -> axum::response::Response {
+    match serde_yaml::to_string(policy) {
+        Ok(yaml) => {
+            let mut headers = tag_headers(tag);
+            headers.insert(header::CONTENT_TYPE, "text/yaml; charset=utf-8".parse().unwrap());
+            (status, headers, yaml).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// `ETag` and `x-guardrail-policy-version` of a policy.
+pub(crate) fn tag_headers(tag: &PolicyTag) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ETAG, tag.etag().parse().unwrap());
+    if let Some(v) = tag.version {
+        headers.insert("x-guardrail-policy-version", v.into());
+    }
+    headers
+}
+
+async fn apply_policy_yaml(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    body: String,
+) -> impl IntoResponse {
+    // Parse YAML -> PolicyFile, reporting every error with its location
+    let policy = match parse_policy(&body) {
+        Ok(p) => p,
+        Err(errors) => return invalid_policy(errors),
+    };
+
+    // store.apply_policy() compiles/validates before recording, swapping & persisting
+    let expected = if_match(&headers);
+    match st
+        .store
+        .apply_policy(policy, change_meta(&headers), expected.as_deref())
+        .await
+    {
+        Ok(v) => applied(&v),
+        Err(e) => apply_error(&st, e).await,
+    }
+}
+
+/// 400 with every problem found in a submitted policy.
+fn invalid_policy(errors: Vec<PolicyError>) -> axum::response::Response {
+    (StatusCode::BAD_REQUEST, Json(PolicyErrors { errors })).into_response()
+}
+
+/// ETag from `If-Match`; `*` (or no header) means any policy.
+pub(crate) fn if_match(headers: &HeaderMap) -> Option<String> {
+    let tag = headers.get(header::IF_MATCH)?.to_str().ok()?.trim();
+    if tag == "*" {
+        return None;
+    }
+    let tag = tag.strip_prefix("W/").unwrap_or(tag);
+    Some(tag.trim_matches('"').to_string())
+}
+
+/// 412 with the current policy for a stale `If-Match` (so the client can
+/// rebase), 404/409 for edits of missing/existing items, 400 with
+/// `PolicyErrors` for a policy that does not compile, 400 for an invalid
+/// edit, 500 for anything else (recording or persisting the policy).
+pub(crate) async fn apply_error(st: &AppState, e: anyhow::Error) -> axum::response::Response {
+    if e.is::<StalePolicy>() {
+        let (policy, tag) = st.store.get_policy_tagged().await;
+        return policy_response(StatusCode::PRECONDITION_FAILED, &policy, &tag);
+    }
+    let e = match e.downcast::<InvalidPolicy>() {
+        Ok(invalid) => return invalid_policy(invalid.errors),
+        Err(e) => e,
+    };
+    let status = match e.downcast_ref::<PolicyEditError>() {
+        Some(PolicyEditError::NotFound(_)) => StatusCode::NOT_FOUND,
+        Some(PolicyEditError::Conflict(_)) => StatusCode::CONFLICT,
+        Some(PolicyEditError::Invalid(_)) => StatusCode::BAD_REQUEST,
+        None => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string()).into_response()
+}
+
+/// `x-guardrail-author` / `x-guardrail-note`, recorded with the new version.
+pub(crate) fn change_meta(headers: &HeaderMap) -> ChangeMeta {
+    let get = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    ChangeMeta {
+        author: get("x-guardrail-author"),
+        note: get("x-guardrail-note"),
+    }
+}
+
+fn applied(v: &VersionInfo) -> axum::response::Response {
+    (StatusCode::OK, tag_headers(&v.tag()), "applied").into_response()
+}
+
+async fn list_versions(State(st): State<AppState>) -> impl IntoResponse {
+    Json(st.store.list_versions().await)
+}
+
+async fn get_version_yaml(
+    State(st): State<AppState>,
+    Path(version): Path<u64>,
+) -> impl IntoResponse {
+    let v = match st.store.get_version(version).await {
+        Ok(Some(v)) => v,
+        Ok(None) => return (StatusCode::NOT_FOUND, "no such version").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    match serde_yaml::to_string(&v) {
+        Ok(yaml) => {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, "text/yaml; charset=utf-8".parse().unwrap());
+            (StatusCode::OK, headers, yaml).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn diff_versions(
+    State(st): State<AppState>,
+    Path((from, to)): Path<(u64, u64)>,
+) -> impl IntoResponse {
+    let (a, b) = match (st.store.get_version(from).await, st.store.get_version(to).await) {
+        (Ok(Some(a)), Ok(Some(b))) => (a, b),
+        (Err(e), _) | (_, Err(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+        _ => return (StatusCode::NOT_FOUND, "no such version").into_response(),
+    };
+
+    match crate::history::diff(&a, &b) {
+        Ok(d) => (StatusCode::OK, Json(d)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Reactivates an earlier version; it is recorded again as the newest one.
+async fn rollback_policy(
+    State(st): State<AppState>,
+    Path(version): Path<u64>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let expected = if_match(&headers);
+    match st
+        .store
+        .rollback(version, change_meta(&headers), expected.as_deref())
+        .await
+    {
+        Ok(Some(v)) => applied(&v),
+        Ok(None) => (StatusCode::NOT_FOUND, "no such version").into_response(),
+        Err(e) => apply_error(&st, e).await,
+    }
+}
+
+/// Evaluates a corpus against the active policy and a candidate (YAML or
+/// JSON body: `{policy, corpus}`) and reports the items whose verdict,
+/// matched rule or redacted output would change. Nothing is applied.
+async fn dry_run_policy(State(st): State<AppState>, body: String) -> impl IntoResponse {
+    let req: DryRunRequest = match serde_yaml::from_str(&body) {
+        Ok(r) => r,
+        Err(e) => {
+            let kind = match serde_yaml::from_str::<serde_yaml::Value>(&body) {
+                Ok(_) => PolicyErrorKind::Schema,
+                Err(_) => PolicyErrorKind::Syntax,
+            };
+            return invalid_policy(vec![PolicyError::yaml(kind, &e)]);
+        }
+    };
+    if req.corpus.len() > MAX_BATCH_ITEMS {
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("corpus exceeds {MAX_BATCH_ITEMS} items"),
+        )
+            .into_response();
+    }
+    let candidate = match PolicySnapshot::compile(&req.policy) {
+        Ok(s) => Arc::new(s),
+        Err(e) => match e.downcast::<InvalidPolicy>() {
+            Ok(invalid) => return invalid_policy(invalid.errors),
+            Err(e) => return (StatusCode::BAD_REQUEST, format!("{e:#}")).into_response(),
+        },
+    };
+    let active = st.store.snapshot();
+
+    // same request ids on both sides
+    let corpus: Vec<EvalRequest> = req
+        .corpus
+        .into_iter()
+        .map(|mut r| {
+            r.request_id.get_or_insert_with(Uuid::new_v4);
+            r
+        })
+        .collect();
+
+    let before = evaluate_parallel(&st, active, corpus.clone()).await;
+    let after = evaluate_parallel(&st, candidate, corpus).await;
+    match (before, after) {
+        (Ok(before), Ok(after)) => {
+            (StatusCode::OK, Json(crate::dry_run::compare(before, after))).into_response()
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+async fn lint_active_policy(State(st): State<AppState>) -> impl IntoResponse {
     let policy = st.store.get_policy().await;
+    Json(crate::lint::lint(&policy))
+}
+
+/// Lints a candidate policy (YAML body) without applying it.
+async fn lint_policy_yaml(body: String) -> impl IntoResponse {
+    match parse_policy(&body) {
+        Ok(policy) => (StatusCode::OK, Json(crate::lint::lint(&policy))).into_response(),
+        Err(errors) => invalid_policy(errors),
+    }
+}
 
-    match serde_yaml::to_string(&policy) {
+async fn get_shadow_yaml(State(st): State<AppState>) -> impl IntoResponse {
+    let Some(shadow) = st.store.shadow() else {
+        return (StatusCode::NOT_FOUND, "no shadow policy").into_response();
+    };
+
+    match serde_yaml::to_string(&shadow.policy) {
         Ok(yaml) => {
             let mut headers = HeaderMap::new();
             headers.insert(header::CONTENT_TYPE, "text/yaml; charset=utf-8".parse().unwrap());
@@ -48,104 +373,619 @@ async fn get_policy_yaml(State(st): State<AppState>) -> impl IntoResponse {
     }
 }
 
-async fn apply_policy_yaml(State(st): State<AppState>, body: String) -> impl IntoResponse {
-    // Parse YAML -> PolicyFile
-    let policy: PolicyFile = match serde_yaml::from_str(&body) {
+async fn set_shadow_yaml(State(st): State<AppState>, body: String) -> impl IntoResponse {
+    let policy = match parse_policy(&body) {
         Ok(p) => p,
-        Err(e) => return (StatusCode::BAD_REQUEST, format!("invalid yaml: {e}")).into_response(),
+        Err(errors) => return invalid_policy(errors),
     };
 
-    // store.apply_policy() compiles/validates before swapping & persisting
-    match st.store.apply_policy(policy).await {
-        Ok(_) => (StatusCode::OK, "applied").into_response(),
+    match st.store.set_shadow(policy) {
+        Ok(_) => (StatusCode::OK, "shadowing").into_response(),
         Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     }
 }
 
+async fn clear_shadow(State(st): State<AppState>) -> impl IntoResponse {
+    if st.store.clear_shadow() {
+        (StatusCode::OK, "cleared").into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "no shadow policy").into_response()
+    }
+}
+
+async fn shadow_metrics(State(st): State<AppState>) -> impl IntoResponse {
+    match st.store.shadow() {
+        Some(shadow) => (StatusCode::OK, Json(shadow.metrics())).into_response(),
+        None => (StatusCode::NOT_FOUND, "no shadow policy").into_response(),
+    }
+}
+
+async fn promote_shadow(State(st): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    match st.store.promote_shadow(change_meta(&headers)).await {
+        Ok(Some(v)) => applied(&v),
+        Ok(None) => (StatusCode::NOT_FOUND, "no shadow policy").into_response(),
+        Err(e) => apply_error(&st, e).await,
+    }
+}
+
 // -----------------------------
 // Data plane (eval)
 // -----------------------------
 
 async fn eval(State(st): State<AppState>, Json(mut req): Json<EvalRequest>) -> impl IntoResponse {
-    let request_id = req.request_id.unwrap_or_else(Uuid::new_v4);
-    req.request_id = Some(request_id);
+    let request_id = *req.request_id.get_or_insert_with(Uuid::new_v4);
+    let snap = st.store.snapshot();
+    // no slot left: this request is counted as dropped instead of compared
+    let shadow = st.store.shadow().and_then(|s| {
+        let permit = s.try_reserve()?;
+        Some((permit, s, req.clone()))
+    });
 
-    // Stage 1: rules
-    let compiled = st.store.compiled_snapshot().await;
-    let (action, matched_rule, reason) = evaluate_stage1(&compiled, &req);
+    let result = evaluate_request(&st, &snap, req);
 
-    // If Stage 1 blocks, short-circuit (don’t bother masking)
-    if matches!(action, Action::Block) {
-        let resp = EvalResponse {
-            request_id,
-            action,
-            matched_rule,
-            reason,
-            output_text: None,
-            pii: None,
-        };
-        return (StatusCode::OK, Json(resp)).into_response();
+    // Shadow policy runs off the request path; only its disagreements are kept
+    if let Some((permit, shadow, req)) = shadow {
+        let active = into_batch_result(request_id, result.clone());
+        let st = st.clone();
+        tokio::task::spawn_blocking(move || {
+            let candidate = batch_result(&st, &shadow.snapshot, req);
+            shadow.record(&active, &candidate);
+            drop(permit);
+        });
     }
-    // Stage 1.5: semantic similarity (char n-gram)
-    let semantic = st.store.semantic_snapshot().await;
-    if let Some((case_id, score, example)) = crate::semantic::evaluate(&semantic, &req.kind, &req.text) {
-        let resp = EvalResponse {
-            request_id,
-            action: semantic.action.clone(),
-            matched_rule: Some(format!("semantic:{}", case_id)),
-            reason: Some(format!("similarity={:.3} to example: {}", score, example)),
-            output_text: None,
-            pii: None,
-        };
-        return (StatusCode::OK, Json(resp)).into_response();
+
+    match result {
+        Ok(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        Err((status, msg)) => (status, msg).into_response(),
     }
-    // Stage 2a: policy-driven PII redaction
-    let pii_cfg = st.store.pii_config().await;
+}
 
-    // payload guard
-    if req.text.as_bytes().len() > pii_cfg.max_bytes {
+#[derive(Debug, serde::Deserialize)]
+struct ExplainQuery {
+    /// Semantic cases to report, best first.
+    #[serde(default = "default_top_n")]
+    top_n: usize,
+}
+
+fn default_top_n() -> usize {
+    5
+}
+
+/// Evaluates one text like `/v1/eval` and returns the verdict with a trace
+/// of every stage: rules (with skip reasons and per-expression results),
+/// decoded payloads, semantic scores, PII findings and stage timings.
+async fn eval_explain(
+    State(st): State<AppState>,
+    Query(q): Query<ExplainQuery>,
+    Json(mut req): Json<EvalRequest>,
+) -> impl IntoResponse {
+    let request_id = *req.request_id.get_or_insert_with(Uuid::new_v4);
+    if !req.messages.is_empty() {
+        return (StatusCode::BAD_REQUEST, "explain takes a single text, not messages")
+            .into_response();
+    }
+    if req.kind == Kind::ToolCall && req.text.is_empty() {
+        if let Some(call) = &req.tool {
+            req.text = call.arguments_text();
+        }
+    }
+
+    let snap = st.store.snapshot();
+    let mut clock = StageClock::new();
+    let v = match evaluate_text(&st, &snap, &req, snap.pii.max_bytes, &mut clock) {
+        Ok(v) => v,
+        Err((status, msg)) => return (status, msg).into_response(),
+    };
+    let mut verdict = eval_response(request_id, v.outcome, v.output_text, v.pii);
+    verdict.schema_violations = v.schema_violations;
+
+    let trace = explain_trace(&st, &snap, &req, q.top_n, clock.timings);
+    (StatusCode::OK, Json(ExplainResponse { verdict, trace })).into_response()
+}
+
+fn explain_trace(
+    st: &AppState,
+    snap: &PolicySnapshot,
+    req: &EvalRequest,
+    top_n: usize,
+    timings: Vec<StageTiming>,
+) -> EvalTrace {
+    let input = EvalInput::new(req, &snap.normalization);
+    let rules = trace_rules(&snap.compiled, &input);
+
+    let include_text = snap.evaluation.include_match_text;
+    let decoded = st
+        .decoder
+        .decode(&req.text, &snap.decoding)
+        .into_iter()
+        .map(|d| {
+            let layer_req = EvalRequest {
+                text: d.text.clone(),
+                ..req.clone()
+            };
+            let layer = EvalInput::new(&layer_req, &snap.normalization);
+            DecodedTrace {
+                decoded: d.origin(),
+                matched_rules: trace_rules(&snap.compiled, &layer)
+                    .into_iter()
+                    .filter(|r| r.status == RuleStatus::Matched)
+                    .map(|r| r.rule_id)
+                    .collect(),
+                // decoded payloads are echoed under the same flag as matched text
+                text: include_text.then_some(d.text),
+            }
+        })
+        .collect();
+
+    let semantic = &snap.semantic;
+    let semantic_skip = if !semantic.enabled {
+        Some("semantic matching is disabled".to_string())
+    } else if !applies(&semantic.applies_to, req) {
+        Some(format!("applies_to does not cover {}", req.kind.as_str()))
+    } else {
+        None
+    };
+    let semantic_text = if semantic.match_normalized {
+        input.normalized_text()
+    } else {
+        &req.text
+    };
+    let scores = match semantic_skip {
+        Some(_) => vec![],
+        None => crate::semantic::case_scores(semantic, semantic_text)
+            .into_iter()
+            .take(top_n)
+            .map(|(case_id, score, example)| SemanticScore {
+                case_id,
+                score,
+                example,
+            })
+            .collect(),
+    };
+
+    let pii_cfg = &snap.pii;
+    let pii_skip = if !pii_cfg.enabled || !matches!(pii_cfg.mode, PiiMode::Redact) {
+        Some("PII redaction is off".to_string())
+    } else if !applies(&pii_cfg.applies_to, req) {
+        Some(format!("applies_to does not cover {}", req.kind.as_str()))
+    } else {
+        None
+    };
+    let (detected, kept) = match pii_skip {
+        Some(_) => (vec![], vec![]),
+        None => (
+            st.pii_regex.detect(&req.text),
+            detect_pii(&st.pii_regex, pii_cfg, &req.text),
+        ),
+    };
+    // matched text only when the policy allows returning findings
+    let entities = |findings| {
+        let mut found = pii_entities(findings, None);
+        if !pii_cfg.include_findings {
+            found.iter_mut().for_each(|p| p.text.clear());
+        }
+        found
+    };
+
+    EvalTrace {
+        timings,
+        rules,
+        decoded,
+        semantic: SemanticTrace {
+            ran: semantic_skip.is_none(),
+            skip_reason: semantic_skip,
+            threshold: semantic.threshold,
+            scores,
+        },
+        pii: PiiTrace {
+            ran: pii_skip.is_none(),
+            skip_reason: pii_skip,
+            detected: entities(detected),
+            kept: entities(kept),
+        },
+    }
+}
+
+/// Evaluates many requests against one policy snapshot, in parallel.
+/// Results come back in request order; a failing item (e.g. over
+/// `max_bytes`) gets an error entry instead of failing the batch.
+async fn eval_batch(
+    State(st): State<AppState>,
+    Json(reqs): Json<Vec<EvalRequest>>,
+) -> impl IntoResponse {
+    if reqs.len() > MAX_BATCH_ITEMS {
         return (
             StatusCode::PAYLOAD_TOO_LARGE,
-            "text exceeds max_bytes policy",
+            format!("batch exceeds {MAX_BATCH_ITEMS} items"),
         )
             .into_response();
     }
 
-    let (output_text, pii) = evaluate_stage2a(&st.pii_regex, &pii_cfg, &req);
+    let snap = st.store.snapshot();
+    match evaluate_parallel(&st, snap, reqs).await {
+        Ok(results) => (StatusCode::OK, Json(results)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
 
-    let resp = EvalResponse {
-        request_id,
-        action: Action::Allow,
-        matched_rule,
-        reason,
-        output_text,
-        pii,
-    };
+/// Evaluates `reqs` on blocking threads, one chunk per core, keeping order.
+async fn evaluate_parallel(
+    st: &AppState,
+    snap: Arc<PolicySnapshot>,
+    reqs: Vec<EvalRequest>,
+) -> Result<Vec<BatchResult>, tokio::task::JoinError> {
+    let workers = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let chunk_size = reqs.len().div_ceil(workers).max(1);
 
-    (StatusCode::OK, Json(resp)).into_response()
+    let mut reqs = reqs.into_iter();
+    let mut tasks = Vec::new();
+    loop {
+        let chunk: Vec<EvalRequest> = reqs.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        let (st, snap) = (st.clone(), snap.clone());
+        tasks.push(tokio::task::spawn_blocking(move || {
+            chunk
+                .into_iter()
+                .map(|req| batch_result(&st, &snap, req))
+                .collect::<Vec<_>>()
+        }));
+    }
+
+    let mut results = Vec::with_capacity(tasks.len() * chunk_size);
+    for task in tasks {
+        results.extend(task.await?);
+    }
+    Ok(results)
 }
 
-// -----------------------------
-// Stage 1 matching helpers
-// -----------------------------
+fn batch_result(st: &AppState, snap: &PolicySnapshot, mut req: EvalRequest) -> BatchResult {
+    let request_id = *req.request_id.get_or_insert_with(Uuid::new_v4);
+    into_batch_result(request_id, evaluate_request(st, snap, req))
+}
+
+fn into_batch_result(
+    request_id: Uuid,
+    result: Result<EvalResponse, (StatusCode, String)>,
+) -> BatchResult {
+    match result {
+        Ok(resp) => BatchResult::Ok(Box::new(resp)),
+        Err((status, error)) => BatchResult::Err(BatchError {
+            request_id,
+            status: status.as_u16(),
+            error,
+        }),
+    }
+}
+
+/// Runs the full pipeline over a single text or a chat conversation.
+pub(crate) fn evaluate_request(
+    st: &AppState,
+    snap: &PolicySnapshot,
+    req: EvalRequest,
+) -> Result<EvalResponse, (StatusCode, String)> {
+    evaluate_request_within(st, snap, req, snap.pii.max_bytes)
+}
+
+/// `evaluate_request` with its own payload guard, for callers that have
+/// already bounded the text (a finished stream, by `streaming.max_bytes`).
+pub(crate) fn evaluate_request_within(
+    st: &AppState,
+    snap: &PolicySnapshot,
+    mut req: EvalRequest,
+    max_bytes: usize,
+) -> Result<EvalResponse, (StatusCode, String)> {
+    let request_id = req.request_id.unwrap_or_else(Uuid::new_v4);
+    req.request_id = Some(request_id);
 
-fn evaluate_stage1(
-    rules: &[CompiledRule],
+    if !req.messages.is_empty() && !req.text.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "send either text or messages, not both".to_string(),
+        ));
+    }
+
+    // tool calls are evaluated over their arguments unless text is given
+    if req.kind == Kind::ToolCall && req.text.is_empty() {
+        if let Some(call) = &req.tool {
+            req.text = call.arguments_text();
+        }
+    }
+
+    if req.messages.is_empty() {
+        let v = evaluate_text(st, snap, &req, max_bytes, &mut StageClock::new())
+            .map_err(|(status, msg)| (status, msg.to_string()))?;
+        let mut resp = eval_response(request_id, v.outcome, v.output_text, v.pii);
+        resp.schema_violations = v.schema_violations;
+        return Ok(resp);
+    }
+
+    // Chat: every message runs through the pipeline on its own (with its
+    // role), results point back at the message index
+    let mut outcome = Stage1Outcome::default();
+    let mut output_messages = req.messages.clone();
+    let mut rewritten = false;
+    let mut pii: Option<Vec<PiiEntity>> = None;
+    let mut schema_violations = Vec::new();
+
+    for (i, m) in req.messages.iter().enumerate() {
+        // the message text, then each tool call it makes
+        let units = std::iter::once((None, req.for_message(m))).chain(
+            m.tool_calls
+                .iter()
+                .enumerate()
+                .map(|(j, c)| (Some(j), req.for_tool_call(c.to_tool_call()))),
+        );
+
+        for (call, unit) in units {
+            let v = evaluate_text(st, snap, &unit, max_bytes, &mut StageClock::new())
+                .map_err(|(status, msg)| (status, format!("messages[{i}]: {msg}")))?;
+            outcome.merge_message(v.outcome, i);
+            schema_violations.extend(v.schema_violations.into_iter().map(|mut s| {
+                s.message = Some(i);
+                s
+            }));
+
+            if matches!(outcome.action, Action::Block | Action::RequireReview) {
+                let mut resp = eval_response(request_id, outcome, None, None);
+                resp.schema_violations = schema_violations;
+                return Ok(resp);
+            }
+
+            if let Some(text) = v.output_text {
+                let out = &mut output_messages[i];
+                match call {
+                    Some(j) => out.tool_calls[j].function.arguments = redacted_arguments(
+                        &out.tool_calls[j].function.arguments,
+                        text,
+                    ),
+                    None => out.content = text,
+                }
+                rewritten = true;
+            }
+            if let Some(found) = v.pii {
+                pii.get_or_insert_with(Vec::new).extend(found.into_iter().map(|mut p| {
+                    p.message = Some(i);
+                    p
+                }));
+            }
+        }
+    }
+
+    let mut resp = eval_response(request_id, outcome, None, pii);
+    resp.output_messages = rewritten.then_some(output_messages);
+    resp.schema_violations = schema_violations;
+
+    Ok(resp)
+}
+
+/// Redacted tool-call arguments, in the same form (JSON string or object)
+/// as the original.
+fn redacted_arguments(original: &serde_json::Value, text: String) -> serde_json::Value {
+    match original {
+        serde_json::Value::String(_) => serde_json::Value::String(text),
+        _ => serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text)),
+    }
+}
+
+/// Pipeline result for one text.
+struct TextVerdict {
+    outcome: Stage1Outcome,
+    output_text: Option<String>,
+    pii: Option<Vec<PiiEntity>>,
+    schema_violations: Vec<SchemaViolation>,
+}
+
+impl TextVerdict {
+    fn decided(outcome: Stage1Outcome, schema_violations: Vec<SchemaViolation>) -> Self {
+        Self {
+            outcome,
+            output_text: None,
+            pii: None,
+            schema_violations,
+        }
+    }
+}
+
+/// Wall time per pipeline stage, for `/v1/eval/explain`.
+struct StageClock {
+    last: Instant,
+    timings: Vec<StageTiming>,
+}
+
+impl StageClock {
+    fn new() -> Self {
+        Self {
+            last: Instant::now(),
+            timings: vec![],
+        }
+    }
+
+    /// Records the time since the previous lap as `stage`.
+    fn lap(&mut self, stage: &'static str) {
+        let now = Instant::now();
+        self.timings.push(StageTiming {
+            stage,
+            micros: (now - self.last).as_micros() as u64,
+        });
+        self.last = now;
+    }
+}
+
+/// Runs Stage 1 (plain and decoded), 1b, 1.5 and 2a over `req.text`;
+/// texts over `max_bytes` are rejected before PII detection.
+fn evaluate_text(
+    st: &AppState,
+    snap: &PolicySnapshot,
     req: &EvalRequest,
-) -> (Action, Option<String>, Option<String>) {
-    for r in rules {
-        if !applies(&r.applies_to, &req.kind) {
-            continue;
+    max_bytes: usize,
+    clock: &mut StageClock,
+) -> Result<TextVerdict, (StatusCode, &'static str)> {
+    // Canonical text for `normalized_text` rules / semantic
+    let input = EvalInput::new(req, &snap.normalization);
+    clock.lap("normalization");
+
+    // Stage 1: rules
+    let evaluation = snap.evaluation_for(req);
+    let mut outcome = evaluate_stage1(&snap.compiled, &input, &evaluation);
+    clock.lap("stage1");
+
+    // Stage 1 again on encoded payloads (base64/hex/URL/ROT13)
+    let decoded = st.decoder.decode(&req.text, &snap.decoding);
+    evaluate_decoded(
+        &snap.compiled,
+        req,
+        &decoded,
+        &snap.normalization,
+        &evaluation,
+        &mut outcome,
+    );
+    clock.lap("decoding");
+
+    // If Stage 1 blocks or needs review, short-circuit (don’t bother masking)
+    if matches!(outcome.action, Action::Block | Action::RequireReview) {
+        return Ok(TextVerdict::decided(outcome, vec![]));
+    }
+
+    // Stage 1b: structured responses must match their JSON Schema
+    let mut schema_violations = Vec::new();
+    if let Some((schema, violations)) =
+        crate::response_schema::evaluate(&snap.schemas, req, &req.text)
+    {
+        let rule_id = format!("schema:{}", schema.id);
+        let reason = format!("{} JSON Schema violation(s)", violations.len());
+
+        match schema.action {
+            Action::Flag => outcome.flags.push(rule_id.clone()),
+            Action::Warn => outcome.warnings.push(
+                schema
+                    .description
+                    .clone()
+                    .unwrap_or_else(|| format!("response does not match schema {}", schema.id)),
+            ),
+            _ => {}
         }
-        if r.when_any.iter().any(|m| match_one(m, req)) {
-            let reason = r
-                .description
-                .clone()
-                .or_else(|| Some("matched".to_string()));
-            return (r.action.clone(), Some(r.id.clone()), reason);
+        outcome.escalate(&schema.action, &rule_id, Some(reason));
+        schema_violations = violations;
+
+        if schema.action.is_terminal() {
+            clock.lap("response_schema");
+            return Ok(TextVerdict::decided(outcome, schema_violations));
         }
     }
-    (Action::Allow, None, None)
+    clock.lap("response_schema");
+
+    let pii_cfg = &snap.pii;
+
+    // Stage 1.5: semantic similarity (char n-gram), plain text first
+    let semantic = &snap.semantic;
+    let semantic_text = if semantic.match_normalized {
+        input.normalized_text()
+    } else {
+        &req.text
+    };
+    let semantic_hit = std::iter::once((semantic_text, None))
+        .chain(decoded.iter().map(|d| (d.text.as_str(), Some(d))))
+        .find_map(|(t, layer)| {
+            crate::semantic::evaluate(semantic, req, t).map(|hit| (hit, layer))
+        });
+    if let Some(((case_id, score, example), layer)) = semantic_hit {
+        let rule_id = format!("semantic:{}", case_id);
+        let reason = format!("similarity={:.3} to example: {}", score, example);
+
+        match semantic.action {
+            Action::Flag => outcome.flags.push(rule_id.clone()),
+            Action::Warn => outcome.warnings.push(
+                semantic
+                    .cases
+                    .iter()
+                    .find(|c| c.id == case_id)
+                    .and_then(|c| c.description.clone())
+                    .unwrap_or_else(|| rule_id.clone()),
+            ),
+            // no spans for a semantic hit: the whole text (or encoded segment) is the match
+            Action::Redact => outcome.redact([layer.map_or(0..req.text.len(), |d| d.span.clone())]),
+            Action::Allow | Action::Block | Action::RequireReview => {}
+        }
+        if outcome.escalate(&semantic.action, &rule_id, Some(reason)) {
+            outcome.decoded = layer.map(|d| d.origin());
+        }
+
+        if semantic.action.is_terminal() {
+            clock.lap("semantic");
+            return Ok(TextVerdict::decided(outcome, schema_violations));
+        }
+    }
+    clock.lap("semantic");
+
+    // Stage 2a: policy-driven PII redaction
+
+    // payload guard
+    if req.text.as_bytes().len() > max_bytes {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "text exceeds max_bytes policy"));
+    }
+
+    // PII inside an encoded payload redacts the whole encoded segment
+    let mut decoded_pii = Vec::new();
+    if pii_should_run(pii_cfg, req) {
+        for d in &decoded {
+            let findings = detect_pii(&st.pii_regex, pii_cfg, &d.text);
+            if !findings.is_empty() {
+                outcome.redact([d.span.clone()]);
+                decoded_pii.extend(pii_entities(findings, Some(d.origin())));
+            }
+        }
+    }
+
+    // `redact` rules (and stripped characters) rewrite the text the later stages see
+    let text = (!outcome.redactions.is_empty() || !outcome.strips.is_empty()).then(|| {
+        redact_spans(
+            &req.text,
+            &outcome.redactions,
+            &outcome.strips,
+            &pii_cfg.redaction_token,
+        )
+    });
+
+    let input = text.as_deref().unwrap_or(&req.text);
+    let (masked, mut pii) = evaluate_stage2a(&st.pii_regex, pii_cfg, req, input);
+    let output_text = masked.or(text);
+    if pii_cfg.include_findings && !decoded_pii.is_empty() {
+        pii.get_or_insert_with(Vec::new).extend(decoded_pii);
+    }
+    clock.lap("pii");
+
+    Ok(TextVerdict {
+        outcome,
+        output_text,
+        pii,
+        schema_violations,
+    })
+}
+
+pub(crate) fn eval_response(
+    request_id: Uuid,
+    outcome: Stage1Outcome,
+    output_text: Option<String>,
+    pii: Option<Vec<PiiEntity>>,
+) -> EvalResponse {
+    EvalResponse {
+        request_id,
+        action: outcome.action,
+        matched_rule: outcome.matched_rule,
+        reason: outcome.reason,
+        output_text,
+        pii,
+        flags: outcome.flags,
+        warnings: outcome.warnings,
+        evidence: outcome.evidence,
+        matches: (!outcome.matches.is_empty()).then_some(outcome.matches),
+        decoded: outcome.decoded,
+        message: outcome.message,
+        output_messages: None,
+        schema_violations: vec![],
+    }
 }
 
 // -----------------------------
@@ -154,53 +994,28 @@ fn evaluate_stage1(
 
 fn evaluate_stage2a(
     detector: &PiiRegexDetector,
-    pii_cfg: &crate::policy::PiiConfig,
+    pii_cfg: &PiiConfig,
     req: &EvalRequest,
-) -> (Option<String>, Option<Vec<crate::policy::PiiEntity>>) {
-    let pii_should_run = pii_cfg.enabled
-        && applies(&pii_cfg.applies_to, &req.kind)
-        && matches!(pii_cfg.mode, PiiMode::Redact);
-
-    if !pii_should_run {
+    text: &str,
+) -> (Option<String>, Option<Vec<PiiEntity>>) {
+    if !pii_should_run(pii_cfg, req) {
         return (None, None);
     }
 
-    // Detect all PII types
-    let all_findings = detector.detect(&req.text);
-
-    // Filter by enabled detectors
-    let findings: Vec<_> = all_findings
-        .into_iter()
-        .filter(|f| match f.pii_type {
-            PiiType::Email => pii_cfg.detectors.email,
-            PiiType::Ip => pii_cfg.detectors.ip,
-            PiiType::CreditCard => pii_cfg.detectors.credit_card,
-            PiiType::Phone => pii_cfg.detectors.phone,
-        })
-        .collect();
+    let findings = detect_pii(detector, pii_cfg, text);
 
     if findings.is_empty() {
         return (None, None);
     }
 
     // Apply redactions
-    let mut masked = req.text.clone();
+    let mut masked = text.to_string();
     for f in findings.iter().rev() {
         masked.replace_range(f.start..f.end, &pii_cfg.redaction_token);
     }
 
     let pii = if pii_cfg.include_findings {
-        let pii_entities = findings
-            .into_iter()
-            .map(|f| crate::policy::PiiEntity {
-                entity_type: format!("{:?}", f.pii_type),
-                start: f.start,
-                end: f.end,
-                score: 1.0,
-                text: f.text,
-            })
-            .collect::<Vec<_>>();
-        Some(pii_entities)
+        Some(pii_entities(findings, None))
     } else {
         None
     };
@@ -208,31 +1023,192 @@ fn evaluate_stage2a(
     (Some(masked), pii)
 }
 
-// -----------------------------
-// Matching helpers
-// -----------------------------
+pub(crate) fn pii_should_run(pii_cfg: &PiiConfig, req: &EvalRequest) -> bool {
+    pii_cfg.enabled && applies(&pii_cfg.applies_to, req) && matches!(pii_cfg.mode, PiiMode::Redact)
+}
 
-fn applies(applies_to: &AppliesTo, kind: &Kind) -> bool {
-    match (applies_to, kind) {
-        (AppliesTo::Both, _) => true,
-        (AppliesTo::Prompt, Kind::Prompt) => true,
-        (AppliesTo::Response, Kind::Response) => true,
-        _ => false,
-    }
+/// Detects all PII types, keeping those whose detector is enabled.
+pub(crate) fn detect_pii(
+    detector: &PiiRegexDetector,
+    pii_cfg: &PiiConfig,
+    text: &str,
+) -> Vec<Finding> {
+    detector
+        .detect(text)
+        .into_iter()
+        .filter(|f| match f.pii_type {
+            PiiType::Email => pii_cfg.detectors.email,
+            PiiType::Ip => pii_cfg.detectors.ip,
+            PiiType::CreditCard => pii_cfg.detectors.credit_card,
+            PiiType::Phone => pii_cfg.detectors.phone,
+        })
+        .collect()
 }
 
-fn field_value<'a>(field: &crate::policy::Field, req: &'a EvalRequest) -> &'a str {
-    match field {
-        crate::policy::Field::Text => req.text.as_str(),
-        crate::policy::Field::Tenant => req.tenant.as_deref().unwrap_or(""),
-        crate::policy::Field::Model => req.model.as_deref().unwrap_or(""),
-    }
+pub(crate) fn pii_entities(findings: Vec<Finding>, decoded: Option<DecodedFrom>) -> Vec<PiiEntity> {
+    findings
+        .into_iter()
+        .map(|f| PiiEntity {
+            entity_type: format!("{:?}", f.pii_type),
+            start: f.start,
+            end: f.end,
+            score: 1.0,
+            text: f.text,
+            decoded: decoded.clone(),
+            message: None,
+        })
+        .collect()
 }
 
-fn match_one(m: &CompiledMatch, req: &EvalRequest) -> bool {
-    match m {
-        CompiledMatch::Exact { field, value } => field_value(field, req) == value,
-        CompiledMatch::Regex { field, re, .. } => re.is_match(field_value(field, req)),
-        CompiledMatch::Keywords { field, ac, .. } => ac.is_match(field_value(field, req)),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use tempfile::TempDir;
+
+    const POLICY: &str = r#"
+rules:
+  - id: no-secrets
+    applies_to: prompt
+    action: block
+    priority: 1
+    when:
+      any:
+        - {type: keywords, field: text, values: [top secret]}
+pii:
+  enabled: true
+  applies_to: both
+  mode: redact
+  redaction_token: "[X]"
+  detectors: {email: true}
+  max_bytes: 64
+  include_findings: false
+"#;
+
+    async fn state(dir: &TempDir) -> AppState {
+        let path = dir.path().join("policy.yaml");
+        tokio::fs::write(&path, POLICY).await.unwrap();
+        AppState {
+            store: RuleStore::load(path).await.unwrap(),
+            pii_regex: PiiRegexDetector::new().unwrap(),
+            decoder: PayloadDecoder::new().unwrap(),
+            upstream: None,
+        }
+    }
+
+    fn batch(texts: &[String]) -> Vec<EvalRequest> {
+        texts
+            .iter()
+            .map(|t| serde_json::from_value(json!({"text": t})).unwrap())
+            .collect()
+    }
+
+    async fn serve(st: AppState) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(st)).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn batch_keeps_order_and_reports_item_errors() {
+        let dir = TempDir::new().unwrap();
+        let base = serve(state(&dir).await).await;
+
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        let body = json!([
+            {"request_id": ids[0], "text": "what is the weather"},
+            {"request_id": ids[1], "text": "x".repeat(65)},
+            {"request_id": ids[2], "text": "this is top secret"},
+        ]);
+        let resp = reqwest::Client::new()
+            .post(format!("{base}/v1/eval/batch"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let results: Vec<Value> = resp.json().await.unwrap();
+
+        assert_eq!(results.len(), 3);
+        for (r, id) in results.iter().zip(&ids) {
+            assert_eq!(r["request_id"], json!(id));
+        }
+        assert_eq!(results[0]["action"], "allow");
+        assert_eq!(results[1]["status"], 413);
+        assert_eq!(results[1]["error"], "text exceeds max_bytes policy");
+        assert_eq!(results[2]["action"], "block");
+    }
+
+    #[tokio::test]
+    async fn batch_evaluates_against_one_snapshot() {
+        let dir = TempDir::new().unwrap();
+        let st = state(&dir).await;
+        let snap = st.store.snapshot();
+
+        // the rule is gone from the store before the batch runs
+        let mut policy = st.store.get_policy().await;
+        policy.rules.clear();
+        st.store
+            .apply_policy(policy, ChangeMeta::default(), None)
+            .await
+            .unwrap();
+
+        // enough items to be spread over every worker
+        let texts: Vec<String> = (0..64).map(|i| format!("top secret {i}")).collect();
+        let results = evaluate_parallel(&st, snap, batch(&texts)).await.unwrap();
+        assert_eq!(results.len(), texts.len());
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, BatchResult::Ok(resp) if resp.action == Action::Block)));
+    }
+
+    #[tokio::test]
+    async fn edits_that_do_not_compile_report_policy_errors() {
+        let dir = TempDir::new().unwrap();
+        let base = serve(state(&dir).await).await;
+        let client = reqwest::Client::new();
+        let bad_rule = json!({
+            "id": "bad-regex",
+            "applies_to": "prompt",
+            "action": "block",
+            "priority": 2,
+            "when": {"any": [{"type": "regex", "field": "text", "pattern": "(unclosed"}]},
+        });
+
+        let resp = client
+            .post(format!("{base}/admin/v1/rules"))
+            .json(&bad_rule)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: Value = resp.json().await.unwrap();
+        let error = &body["errors"][0];
+        assert_eq!(error["kind"], "compile");
+        assert_eq!(error["rule_id"], "bad-regex");
+        assert_eq!(error["path"], "/rules/1/when/any/0");
+
+        let mut policy: Value = serde_yaml::from_str(POLICY).unwrap();
+        policy["rules"].as_array_mut().unwrap().push(bad_rule);
+        let resp = client
+            .post(format!("{base}/admin/v1/policy/dry-run"))
+            .json(&json!({"policy": policy, "corpus": [{"text": "hi"}]}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["errors"][0]["path"], "/rules/1/when/any/0");
+
+        let resp = client
+            .post(format!("{base}/admin/v1/policy/dry-run"))
+            .body("corpus: []")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["errors"][0]["kind"], "schema");
     }
 }
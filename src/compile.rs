@@ -1,5 +1,10 @@
-use crate::policy::{Action, AppliesTo, Field, MatchExpr, Rule};
-use aho_corasick::AhoCorasick;
+use crate::normalize::normalize;
+use crate::pii_regex::PiiRegexDetector;
+use crate::policy::{
+    Action, AppliesTo, CharClass, Comparison, Condition, EntropyScope, Field, KeywordMatchKind,
+    LengthUnit, MatchExpr, Normalize, PiiEntityType, Rule, UnicodeCategory, When,
+};
+use aho_corasick::{AhoCorasick, MatchKind};
 use regex::Regex;
 
 #[derive(Clone)]
@@ -9,14 +14,27 @@ pub struct CompiledRule {
     pub applies_to: AppliesTo,
     pub action: Action,
     pub priority: u32,
-    pub when_any: Vec<CompiledMatch>, // OR list
+    pub when: CompiledCond,
+    pub message: Option<String>,
+}
+
+/// Compiled `When` tree. Empty `Any` never matches, empty `All` always does.
+/// Leaves carry their expression index (depth-first: any, all, not).
+#[derive(Clone)]
+pub enum CompiledCond {
+    Match(usize, CompiledMatch),
+    Any(Vec<CompiledCond>),
+    All(Vec<CompiledCond>),
+    Not(Box<CompiledCond>),
 }
 
 #[derive(Clone)]
 pub enum CompiledMatch {
     Exact {
         field: Field,
-        value: String,
+        value: String, // already normalized
+        case_insensitive: bool,
+        normalize: Normalize,
     },
     Regex {
         field: Field,
@@ -25,55 +43,328 @@ pub enum CompiledMatch {
     },
     Keywords {
         field: Field,
-        ac: AhoCorasick,
+        ac: AhoCorasick, // built from normalized values
         raw: Vec<String>,
+        whole_word: bool,
+        normalize: Normalize,
+    },
+    UnicodeAnomaly {
+        field: Field,
+        categories: Vec<UnicodeCategory>, // never empty
+        min_count: usize,
+        strip: bool,
+    },
+    Length {
+        field: Field,
+        unit: LengthUnit,
+        cmp: Comparison,
+    },
+    CharRatio {
+        field: Field,
+        class: CharClass,
+        cmp: Comparison,
+    },
+    Entropy {
+        field: Field,
+        scope: EntropyScope,
+        min_length: usize,
+        cmp: Comparison,
+    },
+    RegexCount {
+        field: Field,
+        re: Regex,
+        cmp: Comparison,
+    },
+    ToolName {
+        field: Field, // always Field::ToolName
+        names: Vec<String>,
+    },
+    Pii {
+        field: Field,
+        detector: PiiRegexDetector,
+        entities: Vec<PiiEntityType>, // never empty
+    },
+    JsonSchema {
+        field: Field,
+        validator: jsonschema::Validator,
     },
 }
 
-pub fn compile_rule(rule: &Rule) -> anyhow::Result<CompiledRule> {
-    let mut compiled = Vec::with_capacity(rule.when.any.len());
+impl CompiledMatch {
+    /// Expression `type` as written in the policy.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CompiledMatch::Exact { .. } => "exact",
+            CompiledMatch::Regex { .. } => "regex",
+            CompiledMatch::Keywords { .. } => "keywords",
+            CompiledMatch::UnicodeAnomaly { .. } => "unicode_anomaly",
+            CompiledMatch::Length { .. } => "length",
+            CompiledMatch::CharRatio { .. } => "char_ratio",
+            CompiledMatch::Entropy { .. } => "entropy",
+            CompiledMatch::RegexCount { .. } => "regex_count",
+            CompiledMatch::ToolName { .. } => "tool_name",
+            CompiledMatch::Pii { .. } => "pii",
+            CompiledMatch::JsonSchema { .. } => "json_schema",
+        }
+    }
 
-    for expr in &rule.when.any {
-        let c = match expr {
-            MatchExpr::Exact { field, value } => CompiledMatch::Exact {
-                field: field.clone(),
-                value: value.clone(),
-            },
-            MatchExpr::Regex { field, pattern } => {
-                let re = Regex::new(pattern)?;
-                CompiledMatch::Regex {
-                    field: field.clone(),
-                    re,
-                    raw: pattern.clone(),
-                }
-            }
-            MatchExpr::Keywords { field, values } => {
-                let ac = AhoCorasick::new(values)?;
-                CompiledMatch::Keywords {
-                    field: field.clone(),
-                    ac,
-                    raw: values.clone(),
-                }
+    pub fn field(&self) -> &Field {
+        match self {
+            CompiledMatch::Exact { field, .. }
+            | CompiledMatch::Regex { field, .. }
+            | CompiledMatch::Keywords { field, .. }
+            | CompiledMatch::UnicodeAnomaly { field, .. }
+            | CompiledMatch::Length { field, .. }
+            | CompiledMatch::CharRatio { field, .. }
+            | CompiledMatch::Entropy { field, .. }
+            | CompiledMatch::RegexCount { field, .. }
+            | CompiledMatch::ToolName { field, .. }
+            | CompiledMatch::Pii { field, .. }
+            | CompiledMatch::JsonSchema { field, .. } => field,
+        }
+    }
+}
+
+impl CompiledCond {
+    /// Whether a match on a suffix of the text implies a match on the whole
+    /// text, so a stream can act on it before the text is complete. Upper
+    /// bounds, ratios, field-scope entropy, `exact`, `json_schema` and `not`
+    /// over the text are only decided on the whole value.
+    pub fn window_safe(&self) -> bool {
+        match self {
+            CompiledCond::Match(_, m) => m.window_safe(),
+            CompiledCond::Any(cs) | CompiledCond::All(cs) => cs.iter().all(Self::window_safe),
+            CompiledCond::Not(c) => !c.reads_text(),
+        }
+    }
+
+    fn reads_text(&self) -> bool {
+        match self {
+            CompiledCond::Match(_, m) => matches!(m.field(), Field::Text | Field::NormalizedText),
+            CompiledCond::Any(cs) | CompiledCond::All(cs) => cs.iter().any(Self::reads_text),
+            CompiledCond::Not(c) => c.reads_text(),
+        }
+    }
+}
+
+impl CompiledMatch {
+    fn window_safe(&self) -> bool {
+        if !matches!(self.field(), Field::Text | Field::NormalizedText) {
+            return true;
+        }
+        match self {
+            CompiledMatch::Regex { .. }
+            | CompiledMatch::Keywords { .. }
+            | CompiledMatch::UnicodeAnomaly { .. }
+            | CompiledMatch::ToolName { .. }
+            | CompiledMatch::Pii { .. } => true,
+            CompiledMatch::Length { cmp, .. } | CompiledMatch::RegexCount { cmp, .. } => {
+                cmp.lower_bounds_only()
             }
-        };
-        compiled.push(c);
+            CompiledMatch::Entropy { scope, .. } => *scope == EntropyScope::Token,
+            CompiledMatch::Exact { .. }
+            | CompiledMatch::CharRatio { .. }
+            | CompiledMatch::JsonSchema { .. } => false,
+        }
     }
+}
 
+/// Tokens shorter than this are too short for a meaningful entropy score.
+const DEFAULT_ENTROPY_MIN_LENGTH: usize = 20;
+
+pub fn compile_rule(rule: &Rule) -> anyhow::Result<CompiledRule> {
     Ok(CompiledRule {
         id: rule.id.clone(),
         description: rule.description.clone(),
         applies_to: rule.applies_to.clone(),
         action: rule.action.clone(),
         priority: rule.priority,
-        when_any: compiled,
+        when: compile_when(&rule.when, &mut 0)?,
+        message: rule.message.clone(),
     })
 }
 
+fn compile_when(when: &When, next_expr: &mut usize) -> anyhow::Result<CompiledCond> {
+    let mut clauses = Vec::with_capacity(3);
+
+    if !when.any.is_empty() {
+        clauses.push(CompiledCond::Any(compile_conditions(&when.any, next_expr)?));
+    }
+    if !when.all.is_empty() {
+        clauses.push(CompiledCond::All(compile_conditions(&when.all, next_expr)?));
+    }
+    if let Some(not) = &when.not {
+        clauses.push(CompiledCond::Not(Box::new(compile_condition(not, next_expr)?)));
+    }
+
+    // A group without clauses can never fire (same as the old empty `any` list).
+    Ok(match clauses.len() {
+        0 => CompiledCond::Any(vec![]),
+        1 => clauses.pop().unwrap(),
+        _ => CompiledCond::All(clauses),
+    })
+}
+
+fn compile_conditions(
+    conds: &[Condition],
+    next_expr: &mut usize,
+) -> anyhow::Result<Vec<CompiledCond>> {
+    conds.iter().map(|c| compile_condition(c, next_expr)).collect()
+}
+
+fn compile_condition(cond: &Condition, next_expr: &mut usize) -> anyhow::Result<CompiledCond> {
+    match cond {
+        Condition::Match(expr) => {
+            let idx = *next_expr;
+            *next_expr += 1;
+            Ok(CompiledCond::Match(idx, compile_match(expr)?))
+        }
+        Condition::Group(when) => compile_when(when, next_expr),
+    }
+}
+
+pub(crate) fn compile_match(expr: &MatchExpr) -> anyhow::Result<CompiledMatch> {
+    let c = match expr {
+        MatchExpr::Exact {
+            field,
+            value,
+            options,
+        } => CompiledMatch::Exact {
+            field: field.clone(),
+            value: normalize_value(value, &options.normalize),
+            case_insensitive: options.case_insensitive,
+            normalize: options.normalize.clone(),
+        },
+        MatchExpr::Regex { field, pattern } => {
+            let re = Regex::new(pattern)?;
+            CompiledMatch::Regex {
+                field: field.clone(),
+                re,
+                raw: pattern.clone(),
+            }
+        }
+        MatchExpr::Keywords {
+            field,
+            values,
+            options,
+        } => {
+            let patterns: Vec<String> = values
+                .iter()
+                .map(|v| normalize_value(v, &options.normalize))
+                .collect();
+            let ac = AhoCorasick::builder()
+                .ascii_case_insensitive(options.case_insensitive)
+                .match_kind(match options.match_kind {
+                    KeywordMatchKind::Standard => MatchKind::Standard,
+                    KeywordMatchKind::LeftmostFirst => MatchKind::LeftmostFirst,
+                    KeywordMatchKind::LeftmostLongest => MatchKind::LeftmostLongest,
+                })
+                .build(&patterns)?;
+            CompiledMatch::Keywords {
+                field: field.clone(),
+                ac,
+                raw: values.clone(),
+                whole_word: options.whole_word,
+                normalize: options.normalize.clone(),
+            }
+        }
+        MatchExpr::UnicodeAnomaly { field, options } => CompiledMatch::UnicodeAnomaly {
+            field: field.clone(),
+            categories: if options.categories.is_empty() {
+                UnicodeCategory::DEFAULT.to_vec()
+            } else {
+                options.categories.clone()
+            },
+            min_count: options.min_count.max(1),
+            strip: options.strip,
+        },
+        MatchExpr::Length { field, unit, cmp } => CompiledMatch::Length {
+            field: field.clone(),
+            unit: *unit,
+            cmp: checked(cmp, "length")?,
+        },
+        MatchExpr::CharRatio { field, class, cmp } => CompiledMatch::CharRatio {
+            field: field.clone(),
+            class: *class,
+            cmp: checked(cmp, "char_ratio")?,
+        },
+        MatchExpr::Entropy {
+            field,
+            scope,
+            min_length,
+            cmp,
+        } => CompiledMatch::Entropy {
+            field: field.clone(),
+            scope: *scope,
+            min_length: min_length.unwrap_or(match scope {
+                EntropyScope::Field => 0,
+                EntropyScope::Token => DEFAULT_ENTROPY_MIN_LENGTH,
+            }),
+            cmp: checked(cmp, "entropy")?,
+        },
+        MatchExpr::RegexCount {
+            field,
+            pattern,
+            cmp,
+        } => CompiledMatch::RegexCount {
+            field: field.clone(),
+            re: Regex::new(pattern)?,
+            cmp: checked(cmp, "regex_count")?,
+        },
+        MatchExpr::ToolName { names } => CompiledMatch::ToolName {
+            field: Field::ToolName,
+            names: names.clone(),
+        },
+        MatchExpr::Pii { field, entities } => CompiledMatch::Pii {
+            field: field.clone(),
+            detector: PiiRegexDetector::new()?,
+            entities: if entities.is_empty() {
+                vec![
+                    PiiEntityType::Email,
+                    PiiEntityType::Ip,
+                    PiiEntityType::CreditCard,
+                    PiiEntityType::Phone,
+                ]
+            } else {
+                entities.clone()
+            },
+        },
+        MatchExpr::JsonSchema { field, schema } => CompiledMatch::JsonSchema {
+            field: field.clone(),
+            validator: jsonschema::validator_for(schema)
+                .map_err(|e| anyhow::anyhow!("invalid json_schema: {e}"))?,
+        },
+    };
+    Ok(c)
+}
+
+fn checked(cmp: &Comparison, expr_type: &str) -> anyhow::Result<Comparison> {
+    if cmp.is_empty() {
+        anyhow::bail!("{expr_type} expression needs at least one of gt, gte, lt, lte, eq");
+    }
+    Ok(cmp.clone())
+}
+
+fn normalize_value(value: &str, opts: &Normalize) -> String {
+    if opts.is_off() {
+        value.to_string()
+    } else {
+        normalize(value, opts).text
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::policy::{Action, AppliesTo, Field, MatchExpr, When, Rule};
 
+    fn any_items(compiled: &CompiledRule) -> &[CompiledCond] {
+        match &compiled.when {
+            CompiledCond::Any(items) => items,
+            _ => panic!("Expected Any group"),
+        }
+    }
+
     #[test]
     fn compile_exact_match_rule() {
         let rule = Rule {
@@ -82,20 +373,21 @@ mod tests {
             applies_to: AppliesTo::Prompt,
             action: Action::Block,
             priority: 10,
-            when: When {
-                any: vec![MatchExpr::Exact {
-                    field: Field::Text,
-                    value: "dangerous".to_string(),
-                }],
-            },
+            enabled: true,
+            message: None,
+            when: When::any_of(vec![MatchExpr::Exact {
+                field: Field::Text,
+                value: "dangerous".to_string(),
+                options: Default::default(),
+            }]),
         };
 
         let compiled = compile_rule(&rule).unwrap();
         assert_eq!(compiled.id, "test-exact");
-        assert_eq!(compiled.when_any.len(), 1);
+        assert_eq!(any_items(&compiled).len(), 1);
         
-        match &compiled.when_any[0] {
-            CompiledMatch::Exact { field, value } => {
+        match &any_items(&compiled)[0] {
+            CompiledCond::Match(_, CompiledMatch::Exact { field, value, .. }) => {
                 assert!(matches!(field, Field::Text));
                 assert_eq!(value, "dangerous");
             }
@@ -111,19 +403,19 @@ mod tests {
             applies_to: AppliesTo::Both,
             action: Action::Block,
             priority: 5,
-            when: When {
-                any: vec![MatchExpr::Regex {
-                    field: Field::Text,
-                    pattern: r"\b(hack|exploit)\b".to_string(),
-                }],
-            },
+            enabled: true,
+            message: None,
+            when: When::any_of(vec![MatchExpr::Regex {
+                field: Field::Text,
+                pattern: r"\b(hack|exploit)\b".to_string(),
+            }]),
         };
 
         let compiled = compile_rule(&rule).unwrap();
-        assert_eq!(compiled.when_any.len(), 1);
+        assert_eq!(any_items(&compiled).len(), 1);
         
-        match &compiled.when_any[0] {
-            CompiledMatch::Regex { field, re, .. } => {
+        match &any_items(&compiled)[0] {
+            CompiledCond::Match(_, CompiledMatch::Regex { field, re, .. }) => {
                 assert!(matches!(field, Field::Text));
                 assert!(re.is_match("try to hack this"));
                 assert!(!re.is_match("hacking around"));
@@ -140,19 +432,20 @@ mod tests {
             applies_to: AppliesTo::Response,
             action: Action::Block,
             priority: 15,
-            when: When {
-                any: vec![MatchExpr::Keywords {
-                    field: Field::Text,
-                    values: vec!["password".to_string(), "secret".to_string()],
-                }],
-            },
+            enabled: true,
+            message: None,
+            when: When::any_of(vec![MatchExpr::Keywords {
+                field: Field::Text,
+                values: vec!["password".to_string(), "secret".to_string()],
+                options: Default::default(),
+            }]),
         };
 
         let compiled = compile_rule(&rule).unwrap();
-        assert_eq!(compiled.when_any.len(), 1);
+        assert_eq!(any_items(&compiled).len(), 1);
         
-        match &compiled.when_any[0] {
-            CompiledMatch::Keywords { field, ac, .. } => {
+        match &any_items(&compiled)[0] {
+            CompiledCond::Match(_, CompiledMatch::Keywords { field, ac, .. }) => {
                 assert!(matches!(field, Field::Text));
                 let text = "enter your password";
                 let matches: Vec<_> = ac.find_iter(text).collect();
@@ -162,6 +455,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn compile_keywords_with_options() {
+        let expr: MatchExpr = serde_yaml::from_str(
+            r#"
+type: keywords
+field: text
+values: ["Ignore   Previous"]
+case_insensitive: true
+normalize:
+  collapse_whitespace: true
+"#,
+        )
+        .unwrap();
+
+        match compile_match(&expr).unwrap() {
+            CompiledMatch::Keywords { ac, normalize, .. } => {
+                assert!(normalize.collapse_whitespace);
+                assert!(ac.is_match("please IGNORE PREVIOUS orders"));
+                assert!(!ac.is_match("please ignore   previous orders"));
+            }
+            _ => panic!("Expected Keywords match"),
+        }
+    }
+
     #[test]
     fn compile_invalid_regex_fails() {
         let rule = Rule {
@@ -170,12 +487,12 @@ mod tests {
             applies_to: AppliesTo::Prompt,
             action: Action::Block,
             priority: 1,
-            when: When {
-                any: vec![MatchExpr::Regex {
-                    field: Field::Text,
-                    pattern: "[invalid(".to_string(), // Invalid regex
-                }],
-            },
+            enabled: true,
+            message: None,
+            when: When::any_of(vec![MatchExpr::Regex {
+                field: Field::Text,
+                pattern: "[invalid(".to_string(), // Invalid regex
+            }]),
         };
 
         assert!(compile_rule(&rule).is_err());
@@ -189,25 +506,154 @@ mod tests {
             applies_to: AppliesTo::Both,
             action: Action::Block,
             priority: 20,
+            enabled: true,
+            message: None,
+            when: When::any_of(vec![
+                MatchExpr::Exact {
+                    field: Field::Text,
+                    value: "exact".to_string(),
+                    options: Default::default(),
+                },
+                MatchExpr::Regex {
+                    field: Field::Text,
+                    pattern: r"regex\d+".to_string(),
+                },
+                MatchExpr::Keywords {
+                    field: Field::Text,
+                    values: vec!["key1".to_string(), "key2".to_string()],
+                    options: Default::default(),
+                },
+            ]),
+        };
+
+        let compiled = compile_rule(&rule).unwrap();
+        assert_eq!(any_items(&compiled).len(), 3);
+    }
+
+    #[test]
+    fn compile_nested_all_any_not() {
+        let yaml = r#"
+all:
+  - type: regex
+    field: text
+    pattern: "secret"
+  - any:
+      - type: exact
+        field: tenant
+        value: acme
+      - type: exact
+        field: tenant
+        value: globex
+not:
+  type: keywords
+  field: text
+  values: [harmless]
+"#;
+        let rule = Rule {
+            id: "nested".to_string(),
+            description: None,
+            applies_to: AppliesTo::Prompt,
+            action: Action::Block,
+            priority: 1,
+            enabled: true,
+            message: None,
+            when: serde_yaml::from_str(yaml).unwrap(),
+        };
+
+        let compiled = compile_rule(&rule).unwrap();
+        match &compiled.when {
+            CompiledCond::All(clauses) => {
+                assert_eq!(clauses.len(), 2);
+                match &clauses[0] {
+                    CompiledCond::All(items) => {
+                        assert_eq!(items.len(), 2);
+                        assert!(matches!(items[1], CompiledCond::Any(ref inner) if inner.len() == 2));
+                    }
+                    _ => panic!("Expected All clause"),
+                }
+                match &clauses[1] {
+                    CompiledCond::Not(inner) => assert!(matches!(**inner, CompiledCond::Match(3, _))),
+                    _ => panic!("Expected Not clause"),
+                }
+            }
+            _ => panic!("Expected All of clauses"),
+        }
+    }
+
+    #[test]
+    fn compile_empty_when_never_matches() {
+        let rule = Rule {
+            id: "empty".to_string(),
+            description: None,
+            applies_to: AppliesTo::Both,
+            action: Action::Block,
+            priority: 1,
+            enabled: true,
+            message: None,
+            when: When::default(),
+        };
+
+        let compiled = compile_rule(&rule).unwrap();
+        assert!(any_items(&compiled).is_empty());
+    }
+
+    #[test]
+    fn compile_nested_invalid_regex_fails() {
+        let rule = Rule {
+            id: "nested-bad".to_string(),
+            description: None,
+            applies_to: AppliesTo::Prompt,
+            action: Action::Block,
+            priority: 1,
+            enabled: true,
+            message: None,
             when: When {
-                any: vec![
-                    MatchExpr::Exact {
-                        field: Field::Text,
-                        value: "exact".to_string(),
-                    },
-                    MatchExpr::Regex {
-                        field: Field::Text,
-                        pattern: r"regex\d+".to_string(),
-                    },
-                    MatchExpr::Keywords {
-                        field: Field::Text,
-                        values: vec!["key1".to_string(), "key2".to_string()],
-                    },
-                ],
+                not: Some(Box::new(Condition::Group(When::any_of(vec![MatchExpr::Regex {
+                    field: Field::Text,
+                    pattern: "(".to_string(),
+                }])))),
+                ..Default::default()
             },
         };
 
-        let compiled = compile_rule(&rule).unwrap();
-        assert_eq!(compiled.when_any.len(), 3);
+        assert!(compile_rule(&rule).is_err());
+    }
+
+    #[test]
+    fn compile_numeric_exprs_require_a_bound() {
+        let rule = |expr: MatchExpr| Rule {
+            id: "numeric".to_string(),
+            description: None,
+            applies_to: AppliesTo::Response,
+            action: Action::Block,
+            priority: 1,
+            enabled: true,
+            message: None,
+            when: When::any_of(vec![expr]),
+        };
+
+        let unbounded = MatchExpr::Length {
+            field: Field::Text,
+            unit: LengthUnit::Chars,
+            cmp: Comparison::default(),
+        };
+        let err = compile_rule(&rule(unbounded)).err().unwrap();
+        assert!(err.to_string().contains("length"));
+
+        let bounded = MatchExpr::Entropy {
+            field: Field::Text,
+            scope: EntropyScope::Token,
+            min_length: None,
+            cmp: Comparison {
+                gt: Some(4.0),
+                ..Default::default()
+            },
+        };
+        match &any_items(&compile_rule(&rule(bounded)).unwrap())[0] {
+            CompiledCond::Match(_, CompiledMatch::Entropy { min_length, .. }) => {
+                assert_eq!(*min_length, DEFAULT_ENTROPY_MIN_LENGTH);
+            }
+            _ => panic!("Expected Entropy"),
+        }
     }
 }
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::policy::{BatchResult, EvalRequest, EvalResponse, PolicyFile};
+
+/// `/admin/v1/policy/dry-run` body.
+#[derive(Debug, Deserialize)]
+pub struct DryRunRequest {
+    /// Candidate policy; compiled but never applied.
+    pub policy: PolicyFile,
+    /// Sample traffic to evaluate under both policies.
+    pub corpus: Vec<EvalRequest>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DryRunReport {
+    pub total: usize,
+    pub changed: usize,
+    /// How often each verdict change happened, e.g. `allow -> block`.
+    pub transitions: BTreeMap<String, usize>,
+    /// Changed items only, in corpus order.
+    pub items: Vec<ItemDiff>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ItemDiff {
+    /// Position in `corpus`.
+    pub index: usize,
+    pub changes: Vec<Change>,
+    pub active: BatchResult,
+    pub candidate: BatchResult,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Action,
+    MatchedRule,
+    /// Redacted `output_text` / `output_messages`.
+    Output,
+    /// The item failed under one policy only, or failed differently.
+    Error,
+}
+
+/// Pairs up results of the same corpus under the active and candidate policy.
+pub fn compare(active: Vec<BatchResult>, candidate: Vec<BatchResult>) -> DryRunReport {
+    let total = active.len();
+    let mut transitions = BTreeMap::new();
+    let mut items = Vec::new();
+
+    for (index, (active, candidate)) in active.into_iter().zip(candidate).enumerate() {
+        let changes = changes(&active, &candidate);
+        if changes.is_empty() {
+            continue;
+        }
+        let (from, to) = (verdict(&active), verdict(&candidate));
+        if from != to {
+            *transitions.entry(format!("{from} -> {to}")).or_insert(0) += 1;
+        }
+        items.push(ItemDiff {
+            index,
+            changes,
+            active,
+            candidate,
+        });
+    }
+
+    DryRunReport {
+        total,
+        changed: items.len(),
+        transitions,
+        items,
+    }
+}
+
+/// What differs between two verdicts for the same item.
+pub(crate) fn changes(active: &BatchResult, candidate: &BatchResult) -> Vec<Change> {
+    match (active, candidate) {
+        (BatchResult::Ok(a), BatchResult::Ok(c)) => {
+            let mut out = Vec::new();
+            if a.action != c.action {
+                out.push(Change::Action);
+            }
+            if a.matched_rule != c.matched_rule {
+                out.push(Change::MatchedRule);
+            }
+            if output(a) != output(c) {
+                out.push(Change::Output);
+            }
+            out
+        }
+        (BatchResult::Err(a), BatchResult::Err(c))
+            if a.status == c.status && a.error == c.error =>
+        {
+            vec![]
+        }
+        _ => vec![Change::Error],
+    }
+}
+
+/// What the caller would forward: the rewritten text or messages.
+fn output(r: &EvalResponse) -> (Option<&str>, Option<serde_json::Value>) {
+    (
+        r.output_text.as_deref(),
+        r.output_messages
+            .as_ref()
+            .and_then(|m| serde_json::to_value(m).ok()),
+    )
+}
+
+/// The action as it is serialized (`allow`, `block`, ...), or `error`.
+pub(crate) fn verdict(r: &BatchResult) -> String {
+    match r {
+        BatchResult::Ok(r) => serde_json::to_value(&r.action)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default(),
+        BatchResult::Err(_) => "error".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::batch_result;
+
+    #[test]
+    fn reports_only_changed_items() {
+        let active = vec![
+            batch_result(r#"{"action": "allow", "matched_rule": null, "reason": null}"#),
+            batch_result(r#"{"action": "allow", "matched_rule": null, "reason": null}"#),
+            batch_result(r#"{"action": "block", "matched_rule": "a", "reason": null}"#),
+            batch_result(
+                r#"{"action": "allow", "matched_rule": null, "reason": null, "output_text": "x"}"#,
+            ),
+            batch_result(r#"{"status": 413, "error": "text exceeds max_bytes policy"}"#),
+        ];
+        let candidate = vec![
+            batch_result(r#"{"action": "allow", "matched_rule": null, "reason": null}"#),
+            batch_result(r#"{"action": "block", "matched_rule": "new", "reason": null}"#),
+            batch_result(r#"{"action": "block", "matched_rule": "b", "reason": null}"#),
+            batch_result(
+                r#"{"action": "allow", "matched_rule": null, "reason": null, "output_text": "REDACTED"}"#,
+            ),
+            batch_result(r#"{"action": "allow", "matched_rule": null, "reason": null}"#),
+        ];
+
+        let report = compare(active, candidate);
+        assert_eq!(report.total, 5);
+        assert_eq!(report.changed, 4);
+
+        let changes: Vec<(usize, Vec<Change>)> = report
+            .items
+            .iter()
+            .map(|i| (i.index, i.changes.clone()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (1, vec![Change::Action, Change::MatchedRule]),
+                (2, vec![Change::MatchedRule]),
+                (3, vec![Change::Output]),
+                (4, vec![Change::Error]),
+            ]
+        );
+        assert_eq!(report.transitions["allow -> block"], 1);
+        assert_eq!(report.transitions["error -> allow"], 1);
+        assert_eq!(report.transitions.len(), 2);
+    }
+
+    #[test]
+    fn parses_yaml_request() {
+        let req: DryRunRequest = serde_yaml::from_str(
+            r#"
+policy:
+  rules: []
+corpus:
+  - {kind: prompt, text: hello}
+  - {messages: [{role: user, content: hi}]}
+"#,
+        )
+        .unwrap();
+        assert!(req.policy.rules.is_empty());
+        assert_eq!(req.corpus.len(), 2);
+        assert_eq!(req.corpus[1].messages[0].content, "hi");
+    }
+}
@@ -0,0 +1,290 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::policy::PolicyFile;
+use crate::store::PolicyTag;
+
+/// Who applied a policy, and why.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeMeta {
+    pub author: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VersionInfo {
+    pub version: u64,
+    /// SHA-256 (hex) of the policy serialized as YAML.
+    pub hash: String,
+    /// RFC 3339, UTC.
+    pub applied_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl VersionInfo {
+    pub fn tag(&self) -> PolicyTag {
+        PolicyTag {
+            hash: self.hash.clone(),
+            version: Some(self.version),
+        }
+    }
+}
+
+/// One file in the history directory.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PolicyVersion {
+    #[serde(flatten)]
+    pub info: VersionInfo,
+    pub policy: PolicyFile,
+}
+
+/// Every applied policy, as immutable numbered files (`000001.yaml`, ...)
+/// in a directory next to the policy file.
+pub struct History {
+    dir: PathBuf,
+    versions: Vec<VersionInfo>,
+}
+
+impl History {
+    /// `configs/policy.yaml` -> `configs/policy.versions/`
+    pub fn dir_for(policy_path: &Path) -> PathBuf {
+        let stem = policy_path
+            .file_stem()
+            .map_or_else(|| "policy".into(), |s| s.to_string_lossy());
+        policy_path.with_file_name(format!("{stem}.versions"))
+    }
+
+    pub async fn open(dir: PathBuf) -> anyhow::Result<Self> {
+        let mut versions = Vec::new();
+        match tokio::fs::read_dir(&dir).await {
+            Ok(mut entries) => {
+                while let Some(entry) = entries.next_entry().await? {
+                    let path = entry.path();
+                    if path.extension().is_none_or(|e| e != "yaml") {
+                        continue;
+                    }
+                    let raw = tokio::fs::read_to_string(&path).await?;
+                    let v: PolicyVersion = serde_yaml::from_str(&raw)
+                        .with_context(|| format!("reading {}", path.display()))?;
+                    versions.push(v.info);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("reading {}", dir.display())),
+        }
+        versions.sort_by_key(|v| v.version);
+
+        Ok(Self { dir, versions })
+    }
+
+    pub fn list(&self) -> &[VersionInfo] {
+        &self.versions
+    }
+
+    pub fn latest(&self) -> Option<&VersionInfo> {
+        self.versions.last()
+    }
+
+    pub async fn get(&self, version: u64) -> anyhow::Result<Option<PolicyVersion>> {
+        if !self.versions.iter().any(|v| v.version == version) {
+            return Ok(None);
+        }
+        let raw = tokio::fs::read_to_string(self.path(version)).await?;
+        Ok(Some(serde_yaml::from_str(&raw)?))
+    }
+
+    /// Stores `policy` as the next version.
+    pub async fn record(
+        &mut self,
+        policy: &PolicyFile,
+        meta: ChangeMeta,
+    ) -> anyhow::Result<VersionInfo> {
+        let info = VersionInfo {
+            version: self.latest().map_or(1, |v| v.version + 1),
+            hash: policy_hash(policy)?,
+            applied_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            author: meta.author,
+            note: meta.note,
+        };
+        let yaml = serde_yaml::to_string(&PolicyVersion {
+            info: info.clone(),
+            policy: policy.clone(),
+        })?;
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // versions are never overwritten
+        let path = self.path(info.version);
+        let mut f = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+            .with_context(|| format!("creating {}", path.display()))?;
+        f.write_all(yaml.as_bytes()).await?;
+        f.flush().await?;
+
+        self.versions.push(info.clone());
+        Ok(info)
+    }
+
+    fn path(&self, version: u64) -> PathBuf {
+        self.dir.join(format!("{version:06}.yaml"))
+    }
+}
+
+pub fn policy_hash(policy: &PolicyFile) -> anyhow::Result<String> {
+    let yaml = serde_yaml::to_string(policy)?;
+    Ok(format!("{:x}", Sha256::digest(yaml.as_bytes())))
+}
+
+#[derive(Debug, Serialize)]
+pub struct PolicyDiff {
+    pub from: u64,
+    pub to: u64,
+    /// Rule ids, in the order they appear in the policy.
+    pub rules_added: Vec<String>,
+    pub rules_removed: Vec<String>,
+    pub rules_changed: Vec<String>,
+    /// Other top-level sections (`pii`, `semantic`, ...) that differ.
+    pub sections_changed: Vec<String>,
+}
+
+pub fn diff(from: &PolicyVersion, to: &PolicyVersion) -> anyhow::Result<PolicyDiff> {
+    let rule_value = |p: &PolicyFile, id: &str| {
+        p.rules
+            .iter()
+            .find(|r| r.id == id)
+            .map(serde_json::to_value)
+            .transpose()
+    };
+
+    let mut rules_removed = Vec::new();
+    let mut rules_changed = Vec::new();
+    for r in &from.policy.rules {
+        match rule_value(&to.policy, &r.id)? {
+            None => rules_removed.push(r.id.clone()),
+            Some(v) if v != serde_json::to_value(r)? => rules_changed.push(r.id.clone()),
+            Some(_) => {}
+        }
+    }
+    let rules_added = to
+        .policy
+        .rules
+        .iter()
+        .filter(|r| !from.policy.rules.iter().any(|f| f.id == r.id))
+        .map(|r| r.id.clone())
+        .collect();
+
+    let (a, b) = (
+        serde_json::to_value(&from.policy)?,
+        serde_json::to_value(&to.policy)?,
+    );
+    let sections_changed = a
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(k, v)| *k != "rules" && b.get(k.as_str()) != Some(v))
+        .map(|(k, _)| k.clone())
+        .collect();
+
+    Ok(PolicyDiff {
+        from: from.info.version,
+        to: to.info.version,
+        rules_added,
+        rules_removed,
+        rules_changed,
+        sections_changed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Rule;
+    use tempfile::TempDir;
+
+    fn policy(yaml: &str) -> PolicyFile {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    const RULES: &str = r#"
+rules:
+  - id: a
+    applies_to: prompt
+    action: block
+    priority: 10
+    when: {any: [{type: keywords, field: text, values: [foo]}]}
+  - id: b
+    applies_to: prompt
+    action: flag
+    priority: 20
+    when: {any: [{type: keywords, field: text, values: [bar]}]}
+"#;
+
+    #[tokio::test]
+    async fn records_numbered_versions_and_reopens() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = History::dir_for(&temp_dir.path().join("policy.yaml"));
+        assert!(dir.ends_with("policy.versions"));
+
+        let mut history = History::open(dir.clone()).await.unwrap();
+        assert!(history.latest().is_none());
+
+        let first = policy(RULES);
+        history.record(&first, ChangeMeta::default()).await.unwrap();
+        let meta = ChangeMeta {
+            author: Some("alice".into()),
+            note: Some("drop b".into()),
+        };
+        let mut second = first.clone();
+        second.rules.pop();
+        let info = history.record(&second, meta).await.unwrap();
+        assert_eq!(info.version, 2);
+        assert_eq!(info.hash, policy_hash(&second).unwrap());
+        assert_ne!(info.hash, history.list()[0].hash);
+
+        let reopened = History::open(dir).await.unwrap();
+        assert_eq!(reopened.list().len(), 2);
+        let v2 = reopened.get(2).await.unwrap().unwrap();
+        assert_eq!(v2.info.author.as_deref(), Some("alice"));
+        assert_eq!(v2.policy.rules.len(), 1);
+        assert!(reopened.get(3).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn diff_reports_rules_and_sections() {
+        let version = |version, policy| PolicyVersion {
+            info: VersionInfo {
+                version,
+                hash: String::new(),
+                applied_at: String::new(),
+                author: None,
+                note: None,
+            },
+            policy,
+        };
+        let from = policy(RULES);
+        let mut to = from.clone();
+        to.rules.remove(0);
+        to.rules[0].priority = 5;
+        to.rules.push(Rule {
+            id: "c".into(),
+            ..to.rules[0].clone()
+        });
+        to.decoding.enabled = !from.decoding.enabled;
+
+        let d = diff(&version(1, from), &version(2, to)).unwrap();
+        assert_eq!(d.rules_removed, vec!["a"]);
+        assert_eq!(d.rules_changed, vec!["b"]);
+        assert_eq!(d.rules_added, vec!["c"]);
+        assert_eq!(d.sections_changed, vec!["decoding"]);
+    }
+}
@@ -0,0 +1,20 @@
+pub mod admin;
+pub mod api;
+pub mod compile;
+pub mod decode;
+pub mod dry_run;
+pub mod evaluator;
+pub mod history;
+pub mod lint;
+pub mod normalize;
+pub mod pii_regex;
+pub mod policy;
+pub mod proxy;
+pub mod response_schema;
+pub mod semantic;
+pub mod shadow;
+pub mod store;
+pub mod stream;
+#[cfg(test)]
+mod test_support;
+pub mod validate;
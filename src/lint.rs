@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::compile::compile_rule;
+use crate::policy::{
+    AppliesTo, Condition, EvalMode, Kind, MatchExpr, PolicyFile, Role, Rule, When,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The policy is broken or a rule can never take effect.
+    Error,
+    /// Probably a mistake, but the policy works as written.
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LintIssue {
+    pub severity: Severity,
+    /// Stable identifier, e.g. `duplicate_id`.
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule_id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LintReport {
+    pub errors: usize,
+    pub warnings: usize,
+    /// Errors first.
+    pub issues: Vec<LintIssue>,
+}
+
+/// Static checks of a policy that compiling alone does not catch.
+pub fn lint(policy: &PolicyFile) -> LintReport {
+    let mut issues = Vec::new();
+
+    duplicate_ids(&policy.rules, &mut issues);
+    for rule in &policy.rules {
+        if let Err(e) = compile_rule(rule) {
+            issues.push(issue(
+                Severity::Error,
+                "invalid_rule",
+                rule,
+                format!("{e:#}"),
+            ));
+        }
+        if is_empty(&rule.when) {
+            issues.push(issue(
+                Severity::Error,
+                "empty_condition",
+                rule,
+                "`when` has no clauses, so the rule never fires".to_string(),
+            ));
+        }
+        check_conditions(rule, &rule.when, &mut issues);
+    }
+    // in all_matches mode later rules are still evaluated and reported
+    if policy.evaluation.mode == EvalMode::FirstMatch {
+        unreachable_rules(&policy.rules, &mut issues);
+    }
+
+    let threshold = policy.semantic.threshold;
+    if !(0.0..=1.0).contains(&threshold) {
+        issues.push(LintIssue {
+            severity: Severity::Error,
+            code: "semantic_threshold",
+            rule_id: None,
+            message: format!("semantic threshold {threshold} is outside [0, 1]"),
+        });
+    }
+
+    issues.sort_by_key(|i| i.severity);
+    LintReport {
+        errors: issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .count(),
+        warnings: issues
+            .iter()
+            .filter(|i| i.severity == Severity::Warning)
+            .count(),
+        issues,
+    }
+}
+
+impl std::fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{severity}[{}]", self.code)?;
+        if let Some(id) = &self.rule_id {
+            write!(f, " rule `{id}`")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+fn issue(severity: Severity, code: &'static str, rule: &Rule, message: String) -> LintIssue {
+    LintIssue {
+        severity,
+        code,
+        rule_id: Some(rule.id.clone()),
+        message,
+    }
+}
+
+fn duplicate_ids(rules: &[Rule], issues: &mut Vec<LintIssue>) {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for r in rules {
+        *seen.entry(&r.id).or_insert(0) += 1;
+    }
+    for r in rules {
+        if let Some(n) = seen.remove(r.id.as_str()).filter(|n| *n > 1) {
+            issues.push(issue(
+                Severity::Error,
+                "duplicate_id",
+                r,
+                format!("{n} rules have this id"),
+            ));
+        }
+    }
+}
+
+fn is_empty(when: &When) -> bool {
+    when.any.is_empty() && when.all.is_empty() && when.not.is_none()
+}
+
+// -----------------------------
+// Per-expression checks
+// -----------------------------
+
+fn check_conditions(rule: &Rule, when: &When, issues: &mut Vec<LintIssue>) {
+    let nested = when.any.iter().chain(&when.all).chain(when.not.as_deref());
+    for cond in nested {
+        match cond {
+            Condition::Group(g) => {
+                if is_empty(g) {
+                    issues.push(issue(
+                        Severity::Warning,
+                        "empty_condition",
+                        rule,
+                        "a nested group has no clauses and never matches".to_string(),
+                    ));
+                }
+                check_conditions(rule, g, issues);
+            }
+            Condition::Match(expr) => check_expr(rule, expr, issues),
+        }
+    }
+}
+
+fn check_expr(rule: &Rule, expr: &MatchExpr, issues: &mut Vec<LintIssue>) {
+    match expr {
+        MatchExpr::Regex { pattern, .. } | MatchExpr::RegexCount { pattern, .. }
+            if regex::Regex::new(pattern).is_ok_and(|re| re.is_match("")) =>
+        {
+            issues.push(issue(
+                Severity::Warning,
+                "empty_match_regex",
+                rule,
+                format!("regex `{pattern}` matches the empty string"),
+            ));
+        }
+        MatchExpr::Keywords {
+            values, options, ..
+        } => {
+            if values.is_empty() {
+                issues.push(issue(
+                    Severity::Warning,
+                    "empty_condition",
+                    rule,
+                    "`keywords` has no values and never matches".to_string(),
+                ));
+            }
+            let fold = |s: &str| {
+                if options.case_insensitive {
+                    s.to_ascii_lowercase()
+                } else {
+                    s.to_string()
+                }
+            };
+            for (i, short) in values.iter().enumerate() {
+                for (j, long) in values.iter().enumerate() {
+                    let contained = if fold(short) == fold(long) {
+                        i < j
+                    } else {
+                        i != j && contains(&fold(long), &fold(short), options.whole_word)
+                    };
+                    if contained {
+                        issues.push(issue(
+                            Severity::Warning,
+                            "overlapping_keywords",
+                            rule,
+                            format!("keyword `{short}` already matches wherever `{long}` does"),
+                        ));
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether `needle` occurs in `haystack` (on word boundaries if `whole_word`).
+fn contains(haystack: &str, needle: &str, whole_word: bool) -> bool {
+    if needle.is_empty() {
+        return false;
+    }
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    haystack.match_indices(needle).any(|(at, _)| {
+        !whole_word
+            || (!is_word(haystack[..at].chars().next_back())
+                && !is_word(haystack[at + needle.len()..].chars().next()))
+    })
+}
+
+// -----------------------------
+// Unreachable rules
+// -----------------------------
+
+/// A rule is unreachable when an earlier terminal rule (evaluation order:
+/// priority, then id) covers every request it applies to and matches
+/// whenever it does. Only conditions that are provably implied are
+/// considered, so this misses some cases but has no false positives.
+fn unreachable_rules(rules: &[Rule], issues: &mut Vec<LintIssue>) {
+    let mut order: Vec<&Rule> = rules.iter().filter(|r| r.enabled).collect();
+    order.sort_by(|a, b| a.priority.cmp(&b.priority).then(a.id.cmp(&b.id)));
+
+    for (i, later) in order.iter().enumerate() {
+        let shadowing = order[..i].iter().find(|earlier| {
+            earlier.action.is_terminal()
+                && covers(&earlier.applies_to, &later.applies_to)
+                && when_implies(&later.when, &earlier.when)
+        });
+        if let Some(earlier) = shadowing {
+            issues.push(issue(
+                Severity::Warning,
+                "unreachable_rule",
+                later,
+                format!(
+                    "never fires: `{}` (priority {}) always matches first",
+                    earlier.id, earlier.priority
+                ),
+            ));
+        }
+    }
+}
+
+fn covers(outer: &AppliesTo, inner: &AppliesTo) -> bool {
+    const KINDS: [Kind; 4] = [
+        Kind::Prompt,
+        Kind::Response,
+        Kind::ToolCall,
+        Kind::ToolResult,
+    ];
+    const ROLES: [Role; 4] = [Role::System, Role::User, Role::Assistant, Role::Tool];
+    KINDS.iter().all(|k| {
+        ROLES
+            .iter()
+            .all(|r| !inner.covers(k, *r) || outer.covers(k, *r))
+    })
+}
+
+/// `b` matching guarantees `a` matches.
+fn when_implies(b: &When, a: &When) -> bool {
+    if is_empty(a) || a.not.is_some() {
+        return false;
+    }
+    (a.any.is_empty() || implies_any(b, &a.any))
+        && a.all
+            .iter()
+            .all(|c| implies_any(b, std::slice::from_ref(c)))
+}
+
+/// `b` matching guarantees one of `conds` matches.
+fn implies_any(b: &When, conds: &[Condition]) -> bool {
+    // a required part of `b` already does, or every alternative of `b` does
+    b.all.iter().any(|x| cond_implies_any(x, conds))
+        || (!b.any.is_empty() && b.any.iter().all(|x| cond_implies_any(x, conds)))
+}
+
+fn cond_implies_any(x: &Condition, conds: &[Condition]) -> bool {
+    match x {
+        Condition::Group(g) => implies_any(g, conds),
+        Condition::Match(e) => conds.iter().any(|c| match c {
+            Condition::Match(f) => expr_implies(e, f),
+            Condition::Group(g) => when_implies(
+                &When {
+                    all: vec![x.clone()],
+                    ..Default::default()
+                },
+                g,
+            ),
+        }),
+    }
+}
+
+fn expr_implies(b: &MatchExpr, a: &MatchExpr) -> bool {
+    match (b, a) {
+        (
+            MatchExpr::Keywords {
+                field: bf,
+                values: bv,
+                options: bo,
+            },
+            MatchExpr::Keywords {
+                field: af,
+                values: av,
+                options: ao,
+            },
+        ) if bf == af && same(bo, ao) => !bv.is_empty() && bv.iter().all(|v| av.contains(v)),
+        _ => same(b, a),
+    }
+}
+
+fn same<T: Serialize>(a: &T, b: &T) -> bool {
+    matches!((serde_json::to_value(a), serde_json::to_value(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_yaml(yaml: &str) -> Vec<(&'static str, Option<String>)> {
+        let policy: PolicyFile = serde_yaml::from_str(yaml).unwrap();
+        lint(&policy)
+            .issues
+            .into_iter()
+            .map(|i| (i.code, i.rule_id))
+            .collect()
+    }
+
+    fn codes(issues: &[(&'static str, Option<String>)], id: &str) -> Vec<&'static str> {
+        issues
+            .iter()
+            .filter(|(_, r)| r.as_deref() == Some(id))
+            .map(|(c, _)| *c)
+            .collect()
+    }
+
+    #[test]
+    fn clean_policy_has_no_issues() {
+        let issues = lint_yaml(
+            r#"
+rules:
+  - id: jailbreak
+    applies_to: prompt
+    action: block
+    priority: 10
+    when: {any: [{type: keywords, field: text, values: [ignore previous instructions]}]}
+  - id: cards
+    applies_to: response
+    action: block
+    priority: 10
+    when: {any: [{type: regex, field: text, pattern: '\d{16}'}]}
+"#,
+        );
+        assert!(issues.is_empty(), "{issues:?}");
+    }
+
+    #[test]
+    fn reports_structural_problems() {
+        let issues = lint_yaml(
+            r#"
+rules:
+  - id: dup
+    applies_to: prompt
+    action: flag
+    priority: 10
+    when: {any: [{type: regex, field: text, pattern: 'a*'}]}
+  - id: dup
+    applies_to: prompt
+    action: flag
+    priority: 20
+    when: {}
+  - id: kw
+    applies_to: prompt
+    action: flag
+    priority: 30
+    when: {any: [{type: keywords, field: text, case_insensitive: true, values: [Secret, top secret]}]}
+  - id: ww
+    applies_to: prompt
+    action: flag
+    priority: 40
+    when: {any: [{type: keywords, field: text, whole_word: true, values: [pass, password]}]}
+  - id: bad
+    applies_to: prompt
+    action: flag
+    priority: 50
+    when: {any: [{type: regex, field: text, pattern: '('}]}
+semantic:
+  enabled: false
+  applies_to: prompt
+  action: block
+  threshold: 1.5
+  cases: []
+"#,
+        );
+        assert_eq!(
+            codes(&issues, "dup"),
+            vec!["duplicate_id", "empty_condition", "empty_match_regex"]
+        );
+        assert_eq!(codes(&issues, "kw"), vec!["overlapping_keywords"]);
+        assert!(codes(&issues, "ww").is_empty());
+        assert_eq!(codes(&issues, "bad"), vec!["invalid_rule"]);
+        assert!(issues.contains(&("semantic_threshold", None)));
+    }
+
+    #[test]
+    fn finds_rules_shadowed_by_earlier_terminal_rules() {
+        let issues = lint_yaml(
+            r#"
+rules:
+  - id: block-all-secrets
+    applies_to: both
+    action: block
+    priority: 1
+    when: {any: [{type: keywords, field: text, values: [secret, password]}]}
+  - id: flag-password
+    applies_to: prompt
+    action: flag
+    priority: 5
+    when:
+      all:
+        - {type: keywords, field: text, values: [password]}
+        - {type: length, field: text, gt: 10}
+  - id: response-only-block
+    applies_to: response
+    action: block
+    priority: 2
+    when: {any: [{type: keywords, field: text, values: [token]}]}
+  - id: prompt-token
+    applies_to: prompt
+    action: block
+    priority: 6
+    when: {any: [{type: keywords, field: text, values: [token]}]}
+  - id: flag-first
+    applies_to: prompt
+    action: flag
+    priority: 0
+    when: {any: [{type: keywords, field: text, values: [hello]}]}
+  - id: hello-again
+    applies_to: prompt
+    action: flag
+    priority: 7
+    when: {any: [{type: keywords, field: text, values: [hello]}]}
+"#,
+        );
+        assert_eq!(codes(&issues, "flag-password"), vec!["unreachable_rule"]);
+        // earlier rule covers responses only
+        assert!(codes(&issues, "prompt-token").is_empty());
+        // earlier rule is not terminal
+        assert!(codes(&issues, "hello-again").is_empty());
+        assert!(codes(&issues, "block-all-secrets").is_empty());
+    }
+
+    #[test]
+    fn all_matches_mode_has_no_unreachable_rules() {
+        let issues = lint_yaml(
+            r#"
+evaluation:
+  mode: all_matches
+rules:
+  - id: block-secrets
+    applies_to: prompt
+    action: block
+    priority: 1
+    when: {any: [{type: keywords, field: text, values: [secret]}]}
+  - id: flag-secrets
+    applies_to: prompt
+    action: flag
+    priority: 2
+    when: {any: [{type: keywords, field: text, values: [secret]}]}
+"#,
+        );
+        assert!(issues.is_empty(), "{issues:?}");
+    }
+}
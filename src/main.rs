@@ -1,19 +1,42 @@
-mod api;
-mod compile;
-mod pii_regex;
-mod policy;
-mod semantic;
-mod store;
-//mod evaluator; // if you extracted stage1 evaluator into its own module
-
-use api::{router, AppState};
-use pii_regex::PiiRegexDetector;
+use guardrail_engine_stage1::{
+    api::{router, AppState},
+    decode::PayloadDecoder,
+    lint,
+    pii_regex::PiiRegexDetector,
+    proxy::Upstream,
+    store::RuleStore,
+    validate,
+};
 use std::path::PathBuf;
-use store::RuleStore;
 use tracing::info;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // `guardrail-engine-stage1 lint <policy.yaml>`: check a policy and exit
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("lint") {
+        let Some(path) = args.get(1) else {
+            anyhow::bail!("usage: guardrail-engine-stage1 lint <policy.yaml>");
+        };
+        let raw = std::fs::read_to_string(path)?;
+        let policy = match validate::parse_policy(&raw) {
+            Ok(policy) => policy,
+            Err(errors) => {
+                for e in &errors {
+                    println!("{e}");
+                }
+                println!("{} error(s), 0 warning(s)", errors.len());
+                std::process::exit(1);
+            }
+        };
+        let report = lint::lint(&policy);
+        for issue in &report.issues {
+            println!("{issue}");
+        }
+        println!("{} error(s), {} warning(s)", report.errors, report.warnings);
+        std::process::exit(i32::from(report.errors > 0));
+    }
+
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
@@ -28,8 +51,25 @@ async fn main() -> anyhow::Result<()> {
     // Stage 2a detector (full masking)
     let pii_regex = PiiRegexDetector::new()?;
 
+    // Encoded-payload decoding (base64/hex/URL/ROT13)
+    let decoder = PayloadDecoder::new()?;
+
+    // OpenAI-compatible proxy mode (/v1/chat/completions)
+    let upstream = match std::env::var("PROXY_UPSTREAM_URL") {
+        Ok(url) => {
+            info!("proxying chat completions to {}", url);
+            Some(Upstream::new(&url, std::env::var("PROXY_UPSTREAM_API_KEY").ok())?)
+        }
+        Err(_) => None,
+    };
+
     // Build HTTP router with shared state
-    let app = router(AppState { store, pii_regex });
+    let app = router(AppState {
+        store,
+        pii_regex,
+        decoder,
+        upstream,
+    });
 
     info!("engine listening on {}", bind);
     let listener = tokio::net::TcpListener::bind(&bind).await?;
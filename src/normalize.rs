@@ -0,0 +1,521 @@
+use std::ops::Range;
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::policy::{NormalizationConfig, Normalize, UnicodeCategory};
+
+/// Normalized copy of a text that remembers where every output byte came from,
+/// so match offsets can be mapped back onto the original.
+#[derive(Debug, Clone)]
+pub struct Normalized {
+    pub text: String,
+    // source byte range for each byte of `text`
+    src: Vec<Range<usize>>,
+    src_len: usize,
+}
+
+impl Normalized {
+    fn identity(text: &str) -> Self {
+        let mut out = Self::empty(text.len());
+        for (i, ch) in text.char_indices() {
+            out.push(ch.encode_utf8(&mut [0; 4]), i..i + ch.len_utf8());
+        }
+        out
+    }
+
+    fn empty(src_len: usize) -> Self {
+        Self {
+            text: String::with_capacity(src_len),
+            src: Vec::with_capacity(src_len),
+            src_len,
+        }
+    }
+
+    /// Maps a byte range of the normalized text onto the original text.
+    pub fn original_range(&self, r: Range<usize>) -> Range<usize> {
+        if r.start >= self.src.len() {
+            return self.src_len..self.src_len;
+        }
+        let start = self.src[r.start].start;
+        if r.is_empty() {
+            return start..start;
+        }
+        start..self.src[r.end - 1].end
+    }
+
+    fn push(&mut self, s: &str, from: Range<usize>) {
+        self.text.push_str(s);
+        self.src.extend(std::iter::repeat_n(from, s.len()));
+    }
+
+    /// Each char with the original byte range it came from.
+    fn chars(&self) -> impl Iterator<Item = (char, Range<usize>)> + '_ {
+        self.text
+            .char_indices()
+            .map(|(i, ch)| (ch, self.original_range(i..i + ch.len_utf8())))
+    }
+
+    /// Rewrites char by char; `f` appends the replacement (nothing drops it).
+    fn map_chars(&self, mut f: impl FnMut(char, &mut String)) -> Self {
+        let mut out = Self::empty(self.src_len);
+        let mut buf = String::new();
+        for (ch, from) in self.chars() {
+            buf.clear();
+            f(ch, &mut buf);
+            out.push(&buf, from);
+        }
+        out
+    }
+}
+
+/// Applies the enabled steps in order: NFKC, punctuation stripping,
+/// whitespace collapsing.
+pub fn normalize(text: &str, opts: &Normalize) -> Normalized {
+    let mut n = Normalized::identity(text);
+    if opts.nfkc {
+        n = nfkc(&n);
+    }
+    if opts.strip_punctuation {
+        n = strip_punctuation(&n);
+    }
+    if opts.collapse_whitespace {
+        n = collapse_whitespace(&n);
+    }
+    n
+}
+
+/// Builds the canonical form used by `normalized_text` rules and semantic
+/// matching. Steps run in the order of the `NormalizationConfig` fields.
+pub fn canonicalize(text: &str, cfg: &NormalizationConfig) -> Normalized {
+    let mut n = Normalized::identity(text);
+    if cfg.strip_invisible {
+        n = n.map_chars(|ch, out| {
+            if !is_invisible(ch) {
+                out.push(ch)
+            }
+        });
+    }
+    if cfg.nfkc {
+        n = nfkc(&n);
+    }
+    if cfg.homoglyphs {
+        n = n.map_chars(|ch, out| out.push(homoglyph(ch).unwrap_or(ch)));
+    }
+    if cfg.lowercase {
+        n = n.map_chars(|ch, out| out.extend(ch.to_lowercase()));
+    }
+    if cfg.leetspeak {
+        n = leetspeak(&n);
+    }
+    if cfg.strip_punctuation {
+        n = strip_punctuation(&n);
+    }
+    if cfg.collapse_whitespace {
+        n = collapse_whitespace(&n);
+    }
+    if cfg.join_spaced_letters {
+        n = join_spaced_letters(&n);
+    }
+    n
+}
+
+// -----------------------------
+// Passes
+// -----------------------------
+
+/// NFKC per base char plus its trailing combining marks (the unit NFKC
+/// composes), so every output char still maps to a source range.
+fn nfkc(n: &Normalized) -> Normalized {
+    let mut out = Normalized::empty(n.src_len);
+    let mut chars = n.chars().peekable();
+    let mut cluster = String::new();
+    let mut buf = String::new();
+
+    while let Some((ch, from)) = chars.next() {
+        cluster.clear();
+        cluster.push(ch);
+        let mut from = from;
+        while let Some((next, next_from)) = chars.next_if(|(c, _)| is_combining_mark(*c)) {
+            cluster.push(next);
+            from.end = next_from.end;
+        }
+        buf.clear();
+        buf.extend(cluster.nfkc());
+        out.push(&buf, from);
+    }
+    out
+}
+
+/// Drops everything that is neither alphanumeric nor whitespace.
+fn strip_punctuation(n: &Normalized) -> Normalized {
+    n.map_chars(|ch, out| {
+        if ch.is_alphanumeric() || ch.is_whitespace() {
+            out.push(ch)
+        }
+    })
+}
+
+/// Runs of whitespace become one space that maps to the whole run.
+fn collapse_whitespace(n: &Normalized) -> Normalized {
+    let mut out = Normalized::empty(n.src_len);
+    let mut last_ws = false;
+
+    for (ch, from) in n.chars() {
+        if !ch.is_whitespace() {
+            last_ws = false;
+            out.push(ch.encode_utf8(&mut [0; 4]), from);
+        } else if last_ws {
+            if let Some(prev) = out.src.last_mut() {
+                prev.end = from.end;
+            }
+        } else {
+            last_ws = true;
+            out.push(" ", from);
+        }
+    }
+    out
+}
+
+/// Rewrites leet digits/symbols, but only inside words that also contain a
+/// letter, so plain numbers ("2024", "8.8.8.8") are left alone. Symbols are
+/// only read as letters when more of the word follows ("!gnore", "p@ss"),
+/// never as trailing punctuation ("instructions!").
+fn leetspeak(n: &Normalized) -> Normalized {
+    let mut out = Normalized::empty(n.src_len);
+    let chars: Vec<(char, Range<usize>)> = n.chars().collect();
+
+    for word in chars.split_inclusive(|(c, _)| c.is_whitespace()) {
+        let has_letter = word.iter().any(|(c, _)| c.is_alphabetic());
+        for (i, (ch, from)) in word.iter().enumerate() {
+            let trailing = is_leet_symbol(*ch) && !letter_follows(&word[i + 1..]);
+            let ch = match leet(*ch) {
+                Some(l) if has_letter && !trailing => l,
+                _ => *ch,
+            };
+            out.push(ch.encode_utf8(&mut [0; 4]), from.clone());
+        }
+    }
+    out
+}
+
+/// Joins runs of three or more single letters separated by single spaces:
+/// "i g n o r e that" becomes "ignore that".
+fn join_spaced_letters(n: &Normalized) -> Normalized {
+    let chars: Vec<(char, Range<usize>)> = n.chars().collect();
+    let is_single = |i: usize| {
+        chars[i].0.is_alphanumeric()
+            && (i == 0 || chars[i - 1].0 == ' ')
+            && chars.get(i + 1).is_none_or(|(c, _)| *c == ' ')
+    };
+
+    // mark the separating spaces inside qualifying runs
+    let mut drop = vec![false; chars.len()];
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_single(i) {
+            i += 1;
+            continue;
+        }
+        let mut j = i;
+        while j + 2 < chars.len() && chars[j + 1].0 == ' ' && is_single(j + 2) {
+            j += 2;
+        }
+        if (j - i) / 2 + 1 >= 3 {
+            for k in (i + 1..j).step_by(2) {
+                drop[k] = true;
+            }
+        }
+        i = j + 1;
+    }
+
+    let mut out = Normalized::empty(n.src_len);
+    for ((ch, from), dropped) in chars.into_iter().zip(drop) {
+        if !dropped {
+            out.push(ch.encode_utf8(&mut [0; 4]), from);
+        }
+    }
+    out
+}
+
+// -----------------------------
+// Character tables
+// -----------------------------
+
+/// Zero-width, bidi control, tag and other format characters that render
+/// as nothing.
+fn is_invisible(ch: char) -> bool {
+    unicode_anomaly(ch).is_some_and(|c| c != UnicodeCategory::Control)
+}
+
+/// Category of an invisible or control character, if it is one.
+pub fn unicode_anomaly(ch: char) -> Option<UnicodeCategory> {
+    Some(match ch {
+        '\u{E0000}'..='\u{E007F}' => UnicodeCategory::Tag,
+        '\u{00AD}'
+        | '\u{180E}'
+        | '\u{200B}'..='\u{200D}'
+        | '\u{2060}'..='\u{2064}'
+        | '\u{FEFF}' => UnicodeCategory::ZeroWidth,
+        '\u{061C}'
+        | '\u{200E}'
+        | '\u{200F}'
+        | '\u{202A}'..='\u{202E}'
+        | '\u{2066}'..='\u{2069}' => UnicodeCategory::Bidi,
+        '\u{FE00}'..='\u{FE0F}' | '\u{E0100}'..='\u{E01EF}' => UnicodeCategory::VariationSelector,
+        '\t' | '\n' | '\r' => return None,
+        c if c.is_control() => UnicodeCategory::Control,
+        _ => return None,
+    })
+}
+
+/// Invisible and control characters in `text`, with their category. The
+/// joiners and presentation selectors that make up emoji sequences (ZWJ
+/// between emoji, VS16 after one) are not anomalies.
+pub fn anomalies(text: &str) -> impl Iterator<Item = (Range<usize>, UnicodeCategory)> + '_ {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    (0..chars.len()).filter_map(move |i| {
+        let (at, ch) = chars[i];
+        let category = unicode_anomaly(ch)?;
+        let prev = i.checked_sub(1).map(|j| chars[j].1);
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+        let in_emoji = match ch {
+            '\u{200D}' => {
+                prev.is_some_and(|c| is_emoji(c) || c == '\u{FE0F}') && next.is_some_and(is_emoji)
+            }
+            '\u{FE0F}' => prev.is_some_and(is_emoji) || next == Some('\u{20E3}'),
+            _ => false,
+        };
+        (!in_emoji).then(|| (at..at + ch.len_utf8(), category))
+    })
+}
+
+/// Emoji (and symbols with an emoji presentation), roughly.
+fn is_emoji(ch: char) -> bool {
+    matches!(ch,
+        '\u{00A9}'
+        | '\u{00AE}'
+        | '\u{203C}'
+        | '\u{2049}'
+        | '\u{2122}'
+        | '\u{2139}'
+        | '\u{2194}'..='\u{21AA}'
+        | '\u{231A}'..='\u{23FF}'
+        | '\u{24C2}'
+        | '\u{25AA}'..='\u{25FE}'
+        | '\u{2600}'..='\u{27BF}'
+        | '\u{2934}'
+        | '\u{2935}'
+        | '\u{2B05}'..='\u{2B55}'
+        | '\u{3030}'
+        | '\u{303D}'
+        | '\u{3297}'
+        | '\u{3299}'
+        | '\u{1F000}'..='\u{1FAFF}')
+}
+
+/// Cyrillic and Greek letters that look like Latin ones.
+fn homoglyph(ch: char) -> Option<char> {
+    Some(match ch {
+        'а' | 'α' => 'a',
+        'в' => 'b',
+        'с' | 'ϲ' => 'c',
+        'ԁ' => 'd',
+        'е' | 'ε' => 'e',
+        'һ' => 'h',
+        'і' | 'ι' => 'i',
+        'ј' => 'j',
+        'κ' => 'k',
+        'м' => 'm',
+        'п' => 'n',
+        'о' | 'ο' => 'o',
+        'р' | 'ρ' => 'p',
+        'ѕ' => 's',
+        'т' | 'τ' => 't',
+        'υ' => 'u',
+        'ν' => 'v',
+        'ѡ' | 'ω' => 'w',
+        'х' | 'χ' => 'x',
+        'у' | 'γ' => 'y',
+        'А' | 'Α' => 'A',
+        'В' | 'Β' => 'B',
+        'С' => 'C',
+        'Е' | 'Ε' => 'E',
+        'Н' | 'Η' => 'H',
+        'І' | 'Ι' => 'I',
+        'Ј' => 'J',
+        'К' | 'Κ' => 'K',
+        'М' | 'Μ' => 'M',
+        'Ν' => 'N',
+        'О' | 'Ο' => 'O',
+        'Р' | 'Ρ' => 'P',
+        'Ѕ' => 'S',
+        'Т' | 'Τ' => 'T',
+        'Х' | 'Χ' => 'X',
+        'У' | 'Υ' => 'Y',
+        'Ζ' => 'Z',
+        _ => return None,
+    })
+}
+
+fn is_leet_symbol(ch: char) -> bool {
+    matches!(ch, '!' | '@' | '$')
+}
+
+/// Whether a letter (or leet digit) comes next, past any further symbols.
+fn letter_follows(rest: &[(char, Range<usize>)]) -> bool {
+    rest.iter()
+        .map(|(c, _)| *c)
+        .find(|c| !is_leet_symbol(*c))
+        .is_some_and(|c| c.is_alphabetic() || leet(c).is_some())
+}
+
+fn leet(ch: char) -> Option<char> {
+    Some(match ch {
+        '0' => 'o',
+        '1' | '!' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        '8' => 'b',
+        '9' => 'g',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all() -> Normalize {
+        Normalize {
+            nfkc: true,
+            collapse_whitespace: true,
+            strip_punctuation: true,
+        }
+    }
+
+    #[test]
+    fn nfkc_folds_full_width_forms() {
+        let opts = Normalize {
+            nfkc: true,
+            ..Default::default()
+        };
+        let n = normalize("ｉｇｎｏｒｅ this", &opts);
+        assert_eq!(n.text, "ignore this");
+        // "ignore" maps back onto the six full-width chars (3 bytes each)
+        assert_eq!(n.original_range(0..6), 0..18);
+    }
+
+    #[test]
+    fn nfkc_composes_combining_marks() {
+        let opts = Normalize {
+            nfkc: true,
+            ..Default::default()
+        };
+        let n = normalize("cafe\u{301}!", &opts);
+        assert_eq!(n.text, "café!");
+        assert_eq!(n.original_range(3..5), 3..6);
+    }
+
+    #[test]
+    fn strips_punctuation_and_collapses_whitespace() {
+        let input = "ignore,   previous\t\n instructions!!";
+        let n = normalize(input, &all());
+        assert_eq!(n.text, "ignore previous instructions");
+
+        let r = n.original_range(7..15);
+        assert_eq!(&input[r], "previous");
+        let r = n.original_range(6..7);
+        assert_eq!(&input[r], "   ");
+    }
+
+    #[test]
+    fn disabled_options_keep_text() {
+        let n = normalize("Keep  it, as-is", &Normalize::default());
+        assert_eq!(n.text, "Keep  it, as-is");
+        assert_eq!(n.original_range(6..8), 6..8);
+    }
+
+    #[test]
+    fn canonicalize_defeats_common_obfuscation() {
+        let cfg = NormalizationConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let cases = [
+            ("Іgnore previous", "ignore previous"), // Cyrillic І
+            ("ig\u{200B}nore previous", "ignore previous"),
+            ("1gn0re previous", "ignore previous"),
+            ("i g n o r e previous", "ignore previous"),
+            ("ＩＧＮＯＲＥ   previous", "ignore previous"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(canonicalize(input, &cfg).text, expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn canonicalize_keeps_numbers_and_short_words() {
+        let cfg = NormalizationConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            canonicalize("call 555 1234 at 8.8.8.8", &cfg).text,
+            "call 555 1234 at 8.8.8.8"
+        );
+        assert_eq!(canonicalize("a b test", &cfg).text, "a b test");
+    }
+
+    #[test]
+    fn leet_symbols_only_inside_words() {
+        let cfg = NormalizationConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let cases = [
+            (
+                "ignore previous instructions!",
+                "ignore previous instructions!",
+            ),
+            ("!gnore the p@ss", "ignore the pass"),
+            ("pa$$word", "password"),
+            ("wow!! email@", "wow!! email@"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(canonicalize(input, &cfg).text, expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn canonical_offsets_map_to_original() {
+        let cfg = NormalizationConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let input = "ok i g n\u{200B} o r e me";
+        let n = canonicalize(input, &cfg);
+        assert_eq!(n.text, "ok ignore me");
+        assert_eq!(&input[n.original_range(3..9)], "i g n\u{200B} o r e");
+    }
+
+    #[test]
+    fn unicode_anomaly_categories() {
+        assert_eq!(unicode_anomaly('\u{E0041}'), Some(UnicodeCategory::Tag));
+        assert_eq!(
+            unicode_anomaly('\u{200D}'),
+            Some(UnicodeCategory::ZeroWidth)
+        );
+        assert_eq!(unicode_anomaly('\u{202E}'), Some(UnicodeCategory::Bidi));
+        assert_eq!(
+            unicode_anomaly('\u{FE0F}'),
+            Some(UnicodeCategory::VariationSelector)
+        );
+        assert_eq!(unicode_anomaly('\u{0007}'), Some(UnicodeCategory::Control));
+        for ch in ['a', ' ', '\n', '\t', 'é', '😀'] {
+            assert_eq!(unicode_anomaly(ch), None);
+        }
+        assert!(!is_invisible('\u{0007}'));
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
@@ -12,23 +13,234 @@ pub struct PolicyFile {
     /// Stage 1.5: semantic similarity matching
     #[serde(default)]
     pub semantic: SemanticConfig,
+
+    /// Stage 1: rule evaluation options
+    #[serde(default)]
+    pub evaluation: EvaluationConfig,
+
+    /// Canonical text form for `normalized_text` rules and semantic matching
+    #[serde(default)]
+    pub normalization: NormalizationConfig,
+
+    /// Decode base64/hex/URL/ROT13 payloads and re-check them
+    #[serde(default)]
+    pub decoding: DecodingConfig,
+
+    /// Stage 1b: JSON Schema checks on structured model responses
+    #[serde(default)]
+    pub response_schemas: Vec<ResponseSchema>,
+
+    /// Incremental evaluation on `/v1/eval/stream`
+    #[serde(default)]
+    pub streaming: StreamingConfig,
+}
+
+/// Obfuscation-resistant canonical form of the request text. Steps run in
+/// field order. Offsets of matches against it map back to the original text.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NormalizationConfig {
+    pub enabled: bool,
+    /// Zero-width, bidi control and tag characters.
+    pub strip_invisible: bool,
+    /// Full-width forms, ligatures, composed accents.
+    pub nfkc: bool,
+    /// Cyrillic/Greek look-alikes to Latin.
+    pub homoglyphs: bool,
+    pub lowercase: bool,
+    /// "1gn0re" -> "ignore" (only in words that contain a letter).
+    pub leetspeak: bool,
+    pub strip_punctuation: bool,
+    pub collapse_whitespace: bool,
+    /// "i g n o r e" -> "ignore" (runs of 3+ single letters).
+    pub join_spaced_letters: bool,
+}
+
+impl Default for NormalizationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            strip_invisible: true,
+            nfkc: true,
+            homoglyphs: true,
+            lowercase: true,
+            leetspeak: true,
+            strip_punctuation: false,
+            collapse_whitespace: true,
+            join_spaced_letters: true,
+        }
+    }
+}
+
+/// Encoded-payload decoding. Segments found in the text are decoded
+/// (recursively, up to `max_depth` layers) and the decoded text is run
+/// through Stage 1 rules, semantic matching and PII detection.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DecodingConfig {
+    pub enabled: bool,
+    pub base64: bool,
+    pub hex: bool,
+    /// Tokens containing `%XX` escapes.
+    pub url: bool,
+    /// Applied to the whole text, so off by default.
+    pub rot13: bool,
+    pub max_depth: usize,
+    /// Shortest encoded segment considered, in bytes.
+    pub min_length: usize,
+    /// Budget for decoded output across all layers.
+    pub max_decoded_bytes: usize,
+}
+
+impl Default for DecodingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            base64: true,
+            hex: true,
+            url: true,
+            rot13: false,
+            max_depth: 2,
+            min_length: 16,
+            max_decoded_bytes: 16 * 1024,
+        }
+    }
+}
+
+/// Streamed text is checked with Stage 1 rules and PII detection as it
+/// arrives; the complete text gets the full pipeline at the end.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StreamingConfig {
+    /// Trailing bytes held back until more text arrives, so matches that
+    /// span a chunk boundary (up to this length) are caught whole.
+    pub holdback_bytes: usize,
+    /// Longest stream accepted, in bytes.
+    pub max_bytes: usize,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            holdback_bytes: 64,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    Base64,
+    Hex,
+    Url,
+    Rot13,
+}
+
+/// Stage 1 config
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct EvaluationConfig {
+    pub mode: EvalMode, // first_match|all_matches (requests may override)
+    pub include_match_text: bool, // echo matched text in evidence spans
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EvalMode {
+    /// Stop at the first terminal rule (allow|block|require_review).
+    #[default]
+    FirstMatch,
+    /// Evaluate every applicable rule and report them all in `matches`.
+    /// The verdict is the same as in `first_match`.
+    AllMatches,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Rule {
     pub id: String,
     pub description: Option<String>,
-    pub applies_to: AppliesTo, // prompt|response|both
-    pub action: Action,        // allow|block
+    pub applies_to: AppliesTo, // prompt|response|both or [roles]
+    pub action: Action,        // allow|block|flag|redact|warn|require_review
     pub priority: u32,         // lower = higher priority
-    pub when: When,            // OR list
+    pub when: When,            // any/all/not, nestable
+
+    /// User-facing text for `warn` (falls back to description).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+
+    /// Disabled rules stay in the policy but are not evaluated.
+    #[serde(default = "default_enabled", skip_serializing_if = "is_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn is_enabled(enabled: &bool) -> bool {
+    *enabled
+}
+
+/// JSON Schema a `kind: response` text must satisfy. The first schema
+/// whose selector matches the request is checked.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ResponseSchema {
+    pub id: String,
+    #[serde(default)]
+    pub selector: SchemaSelector,
+    /// block|require_review|flag|warn
+    #[serde(default = "default_schema_action")]
+    pub action: Action,
+    /// Shown as the warning for `warn`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub schema: serde_json::Value,
+}
+
+fn default_schema_action() -> Action {
+    Action::Block
+}
+
+/// Which requests a response schema covers; every field set must match.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SchemaSelector {
+    /// Exact model name, or a prefix ending in `*` (`gpt-4o*`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, MetadataValue>,
+}
+
+impl SchemaSelector {
+    pub fn matches(&self, req: &EvalRequest) -> bool {
+        let model = self.model.as_deref().is_none_or(|want| {
+            let have = req.model.as_deref().unwrap_or_default();
+            match want.strip_suffix('*') {
+                Some(prefix) => have.starts_with(prefix),
+                None => have == want,
+            }
+        });
+        let tenant = self
+            .tenant
+            .as_ref()
+            .is_none_or(|want| req.tenant.as_ref() == Some(want));
+        let metadata = self
+            .metadata
+            .iter()
+            .all(|(k, v)| req.metadata.get(k) == Some(v));
+        model && tenant && metadata
+    }
 }
 
 /// Stage 2a config
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PiiConfig {
     pub enabled: bool,
-    pub applies_to: AppliesTo, // prompt|response|both
+    pub applies_to: AppliesTo, // prompt|response|both or [roles]
     pub mode: PiiMode,         // redact|off (future: block/report)
     pub redaction_token: String,
     pub detectors: PiiDetectors,
@@ -96,6 +308,10 @@ pub struct SemanticConfig {
     pub ngram_min: Option<usize>,
     #[serde(default)]
     pub ngram_max: Option<usize>,
+
+    /// Compare the `normalization` canonical text instead of the raw text.
+    #[serde(default)]
+    pub match_normalized: bool,
 }
 
 impl Default for SemanticConfig {
@@ -108,6 +324,7 @@ impl Default for SemanticConfig {
             cases: vec![],
             ngram_min: Some(3),
             ngram_max: Some(5),
+            match_normalized: false,
         }
     }
 }
@@ -122,6 +339,7 @@ pub struct SemanticCase {
 #[derive(Debug, Clone, Serialize)]
 pub struct SemanticExample {
     pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub embedding: Option<Vec<f32>>,
 }
 
@@ -168,7 +386,7 @@ impl<'de> Deserialize<'de> for SemanticExample {
                             text = Some(map.next_value()?);
                         }
                         "embedding" => {
-                            embedding = Some(map.next_value()?);
+                            embedding = map.next_value()?;
                         }
                         _ => {
                             let _: serde::de::IgnoredAny = map.next_value()?;
@@ -195,6 +413,29 @@ pub struct PiiEntity {
     // ⚠️ Consider removing this in default mode to avoid leaking raw PII back.
     // We'll keep it in the struct, but control whether it's populated via include_findings.
     pub text: String,
+
+    /// Set when found inside a decoded payload; `start`/`end` are then
+    /// offsets into the decoded text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoded: Option<DecodedFrom>,
+
+    /// Index into `messages` of the message it was found in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<usize>,
+}
+
+/// One way a response failed its JSON Schema.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SchemaViolation {
+    /// Id of the `response_schemas` entry.
+    pub schema: String,
+    /// JSON Pointer to the offending value (`""` for the whole document).
+    pub path: String,
+    pub error: String,
+
+    /// Index into `messages` of the message it was found in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<usize>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -209,59 +450,927 @@ pub struct EvalResponse {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pii: Option<Vec<PiiEntity>>,
+
+    /// Ids of `flag` rules (and semantic cases) that fired.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+
+    /// User-facing messages from `warn` rules.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+
+    /// Rules that shaped the verdict, with where they matched.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub evidence: Vec<RuleMatch>,
+
+    /// Every rule that fired (only in `all_matches` mode).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<Vec<RuleMatch>>,
+
+    /// Decoding layer the verdict came from, if not the plain text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoded: Option<DecodedFrom>,
+
+    /// Index into `messages` of the message that produced the verdict.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<usize>,
+
+    /// `messages` with redactions applied (only when something changed).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_messages: Option<Vec<Message>>,
+
+    /// Why the response failed its `response_schemas` entry.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schema_violations: Vec<SchemaViolation>,
+}
+
+/// `/v1/eval/explain` output: the usual verdict plus how it was reached.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplainResponse {
+    pub verdict: EvalResponse,
+    pub trace: EvalTrace,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalTrace {
+    /// Wall time of each pipeline stage that ran, in order.
+    pub timings: Vec<StageTiming>,
+    /// Every rule in priority order, against the plain text.
+    pub rules: Vec<RuleTrace>,
+    /// Decoded payloads and the rules that matched inside them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub decoded: Vec<DecodedTrace>,
+    pub semantic: SemanticTrace,
+    pub pii: PiiTrace,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StageTiming {
+    pub stage: &'static str,
+    pub micros: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleTrace {
+    pub rule_id: String,
+    pub priority: u32,
+    pub action: Action,
+    pub status: RuleStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<String>,
+    /// An earlier terminal rule already decided, so this one could not
+    /// change the verdict (in `first_match` mode it is not evaluated at all).
+    pub after_decision: bool,
+    /// Result of each match expression on its own (before `not`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exprs: Vec<ExprTrace>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleStatus {
+    /// `applies_to` does not cover the request.
+    Skipped,
+    NoMatch,
+    Matched,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct ExprTrace {
+    /// Depth-first index, as in `MatchSpan::expr`.
+    pub expr: usize,
+    #[serde(rename = "type")]
+    pub expr_type: &'static str,
+    pub field: Field,
+    pub matched: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodedTrace {
+    pub decoded: DecodedFrom,
+    /// Only populated when `evaluation.include_match_text` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub matched_rules: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SemanticTrace {
+    pub ran: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<String>,
+    pub threshold: f32,
+    /// Best score per case, highest first (top N).
+    pub scores: Vec<SemanticScore>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SemanticScore {
+    pub case_id: String,
+    pub score: f32,
+    pub example: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PiiTrace {
+    pub ran: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<String>,
+    /// Everything the regex detector found in the original text.
+    pub detected: Vec<PiiEntity>,
+    /// What is left after the per-type `detectors` switches.
+    pub kept: Vec<PiiEntity>,
+}
+
+/// One `/v1/eval/batch` result: the verdict, or why that item failed.
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum BatchResult {
+    Ok(Box<EvalResponse>),
+    Err(BatchError),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchError {
+    pub request_id: Uuid,
+    /// HTTP status `/v1/eval` would have returned for the item.
+    pub status: u16,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RuleMatch {
+    pub rule_id: String,
+    pub action: Action,
+    pub priority: u32,
+    pub spans: Vec<MatchSpan>,
+
+    /// Set when the rule matched decoded content; spans are then offsets
+    /// into the decoded text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoded: Option<DecodedFrom>,
+
+    /// Index into `messages`; spans are offsets into that message's content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<usize>,
+}
+
+/// A decoded payload: the encodings peeled off (outermost first) and the
+/// span of the encoded segment in the original text.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DecodedFrom {
+    pub encodings: Vec<Encoding>,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Where one match expression hit, as offsets into the value of `field`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MatchSpan {
+    /// Index of the `MatchExpr` in the rule's `when`, numbered depth-first
+    /// through `any`, then `all`, then `not`.
+    pub expr: usize,
+    pub field: Field,
+    pub start: usize, // bytes
+    pub end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+
+    /// Only populated when `evaluation.include_match_text` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// Rule condition. Every clause present must hold: `any` (OR), `all` (AND)
+/// and `not`. Clause entries are match expressions or nested groups.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(deny_unknown_fields)]
 pub struct When {
-    pub any: Vec<MatchExpr>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub any: Vec<Condition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub all: Vec<Condition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not: Option<Box<Condition>>,
+}
+
+#[cfg(test)]
+impl When {
+    /// Flat OR of match expressions (the original `when.any` shape).
+    pub fn any_of(exprs: Vec<MatchExpr>) -> Self {
+        Self {
+            any: exprs.into_iter().map(Condition::Match).collect(),
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
+#[serde(untagged)]
+pub enum Condition {
+    Match(MatchExpr),
+    Group(When),
+}
+
+/// `prompt`, `response`, `both`, `tool_call`, `tool_result`, or a list of
+/// chat roles such as `[user, tool]`. Tool calls and results are opt-in:
+/// only `tool_call`/`tool_result` (or a role list) cover them.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "AppliesToRepr", into = "AppliesToRepr")]
 pub enum AppliesTo {
     Prompt,
     Response,
     Both,
+    ToolCall,
+    ToolResult,
+    Roles(Vec<Role>),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl AppliesTo {
+    pub fn covers(&self, kind: &Kind, role: Role) -> bool {
+        match self {
+            AppliesTo::Both => matches!(kind, Kind::Prompt | Kind::Response),
+            AppliesTo::Prompt => matches!(kind, Kind::Prompt),
+            AppliesTo::Response => matches!(kind, Kind::Response),
+            AppliesTo::ToolCall => matches!(kind, Kind::ToolCall),
+            AppliesTo::ToolResult => matches!(kind, Kind::ToolResult),
+            AppliesTo::Roles(roles) => roles.contains(&role),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum AppliesToRepr {
+    Scope(Scope),
+    Roles(Vec<Role>),
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Scope {
+    Prompt,
+    Response,
+    Both,
+    ToolCall,
+    ToolResult,
+}
+
+impl From<AppliesToRepr> for AppliesTo {
+    fn from(r: AppliesToRepr) -> Self {
+        match r {
+            AppliesToRepr::Scope(Scope::Prompt) => AppliesTo::Prompt,
+            AppliesToRepr::Scope(Scope::Response) => AppliesTo::Response,
+            AppliesToRepr::Scope(Scope::Both) => AppliesTo::Both,
+            AppliesToRepr::Scope(Scope::ToolCall) => AppliesTo::ToolCall,
+            AppliesToRepr::Scope(Scope::ToolResult) => AppliesTo::ToolResult,
+            AppliesToRepr::Roles(roles) => AppliesTo::Roles(roles),
+        }
+    }
+}
+
+impl From<AppliesTo> for AppliesToRepr {
+    fn from(a: AppliesTo) -> Self {
+        match a {
+            AppliesTo::Prompt => AppliesToRepr::Scope(Scope::Prompt),
+            AppliesTo::Response => AppliesToRepr::Scope(Scope::Response),
+            AppliesTo::Both => AppliesToRepr::Scope(Scope::Both),
+            AppliesTo::ToolCall => AppliesToRepr::Scope(Scope::ToolCall),
+            AppliesTo::ToolResult => AppliesToRepr::Scope(Scope::ToolResult),
+            AppliesTo::Roles(roles) => AppliesToRepr::Roles(roles),
+        }
+    }
+}
+
+/// Chat message author, as in OpenAI-style message arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Also OpenAI's `developer`.
+    #[serde(alias = "developer")]
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+impl Role {
+    /// Assistant turns are model output; everything else is model input.
+    pub fn kind(self) -> Kind {
+        match self {
+            Role::Assistant => Kind::Response,
+            Role::Tool => Kind::ToolResult,
+            Role::System | Role::User => Kind::Prompt,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Message {
+    pub role: Role,
+    /// `null` (assistant turns that only call tools) reads as ""; a list of
+    /// content parts reads as its text parts, one per line.
+    #[serde(default, deserialize_with = "message_content")]
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<MessageToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+/// OpenAI `tool_calls` entry.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MessageToolCall {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub call_type: Option<String>,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+impl MessageToolCall {
+    pub fn to_tool_call(&self) -> ToolCall {
+        ToolCall {
+            id: self.id.clone(),
+            name: self.function.name.clone(),
+            arguments: self.function.arguments.clone(),
+        }
+    }
+}
+
+fn message_content<'de, D: serde::Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Content {
+        Text(String),
+        Parts(Vec<ContentPart>),
+    }
+    #[derive(Deserialize)]
+    struct ContentPart {
+        #[serde(default)]
+        text: Option<String>,
+    }
+
+    Ok(match Option::<Content>::deserialize(d)? {
+        None => String::new(),
+        Some(Content::Text(s)) => s,
+        Some(Content::Parts(parts)) => parts
+            .into_iter()
+            .filter_map(|p| p.text)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Action {
     Allow,
     Block,
+    /// Annotate the response, keep evaluating.
+    Flag,
+    /// Replace the matched spans, keep evaluating.
+    Redact,
+    /// Allow, but return a user-facing warning.
+    Warn,
+    /// Stop and route to a human review queue.
+    RequireReview,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "snake_case")]
+impl Action {
+    /// Terminal actions stop rule evaluation; the others accumulate.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Action::Allow | Action::Block | Action::RequireReview)
+    }
+
+    /// Verdict precedence when several actions fire (higher wins).
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Action::Allow => 0,
+            Action::Flag => 1,
+            Action::Warn => 2,
+            Action::Redact => 3,
+            Action::RequireReview => 4,
+            Action::Block => 5,
+        }
+    }
+}
+
+/// Request value a match expression looks at. Written as `text`,
+/// `normalized_text`, `tenant`, `model`, `request_id`, `kind`,
+/// `metadata.<key>`, `tool_name` or `tool_args<jsonpath>`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum Field {
     Text,
+    /// Canonical text from the `normalization` section (the raw text when it
+    /// is disabled). Spans are reported against the original text.
+    NormalizedText,
     Tenant,
     Model,
+    RequestId,
+    /// `prompt`, `response`, `tool_call` or `tool_result`.
+    Kind,
+    /// A key of `EvalRequest::metadata`; missing keys read as "".
+    Metadata(String),
+    /// Name of `EvalRequest::tool`.
+    ToolName,
+    /// Tool-call arguments selected by a JSONPath whose leading `$` is
+    /// spelled `tool_args`: `tool_args.to`, `tool_args..email`,
+    /// `tool_args.recipients[*]`. Selected values are joined by newlines,
+    /// strings as-is and anything else as JSON.
+    ToolArgs(ArgsPath),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgsPath {
+    /// Everything after `tool_args`.
+    pub suffix: String,
+    pub path: serde_json_path::JsonPath,
+}
+
+impl TryFrom<String> for Field {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Ok(match s.as_str() {
+            "text" => Field::Text,
+            "normalized_text" => Field::NormalizedText,
+            "tenant" => Field::Tenant,
+            "model" => Field::Model,
+            "request_id" => Field::RequestId,
+            "kind" => Field::Kind,
+            "tool_name" => Field::ToolName,
+            _ => {
+                if let Some(key) = s.strip_prefix("metadata.").filter(|k| !k.is_empty()) {
+                    Field::Metadata(key.to_string())
+                } else if let Some(suffix) = s.strip_prefix("tool_args") {
+                    let path = serde_json_path::JsonPath::parse(&format!("${suffix}"))
+                        .map_err(|e| format!("invalid tool_args path `{s}`: {e}"))?;
+                    Field::ToolArgs(ArgsPath {
+                        suffix: suffix.to_string(),
+                        path,
+                    })
+                } else {
+                    return Err(format!("unknown field `{s}`"));
+                }
+            }
+        })
+    }
+}
+
+impl From<Field> for String {
+    fn from(f: Field) -> Self {
+        match f {
+            Field::Text => "text".to_string(),
+            Field::NormalizedText => "normalized_text".to_string(),
+            Field::Tenant => "tenant".to_string(),
+            Field::Model => "model".to_string(),
+            Field::RequestId => "request_id".to_string(),
+            Field::Kind => "kind".to_string(),
+            Field::Metadata(key) => format!("metadata.{key}"),
+            Field::ToolName => "tool_name".to_string(),
+            Field::ToolArgs(p) => format!("tool_args{}", p.suffix),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MatchExpr {
-    Exact { field: Field, value: String },
-    Regex { field: Field, pattern: String },
-    Keywords { field: Field, values: Vec<String> },
+    Exact {
+        field: Field,
+        value: String,
+        #[serde(flatten)]
+        options: ExactOptions,
+    },
+    Regex {
+        field: Field,
+        pattern: String,
+    },
+    Keywords {
+        field: Field,
+        values: Vec<String>,
+        #[serde(flatten)]
+        options: KeywordOptions,
+    },
+    /// Invisible or control characters: tag characters, zero-width
+    /// characters, bidi overrides and the like.
+    UnicodeAnomaly {
+        field: Field,
+        #[serde(flatten)]
+        options: AnomalyOptions,
+    },
+    /// Size of the field, e.g. `{unit: chars, gt: 20000}`.
+    Length {
+        field: Field,
+        #[serde(default)]
+        unit: LengthUnit,
+        #[serde(flatten)]
+        cmp: Comparison,
+    },
+    /// Share of characters in `class`, from 0.0 to 1.0.
+    CharRatio {
+        field: Field,
+        class: CharClass,
+        #[serde(flatten)]
+        cmp: Comparison,
+    },
+    /// Shannon entropy in bits per character. With `scope: token` (the
+    /// default) each whitespace-separated token of at least `min_length`
+    /// chars is scored on its own, so a key inside prose still stands out.
+    Entropy {
+        field: Field,
+        #[serde(default)]
+        scope: EntropyScope,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_length: Option<usize>,
+        #[serde(flatten)]
+        cmp: Comparison,
+    },
+    /// Number of (non-overlapping) matches of `pattern`.
+    RegexCount {
+        field: Field,
+        pattern: String,
+        #[serde(flatten)]
+        cmp: Comparison,
+    },
+    /// Tool call name is one of `names` (negate with `not` for allow-lists).
+    ToolName { names: Vec<String> },
+    /// PII found by the Stage 2a detectors; empty `entities` means all.
+    Pii {
+        field: Field,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        entities: Vec<PiiEntityType>,
+    },
+    /// Fires when the field is not JSON valid against `schema`.
+    JsonSchema {
+        field: Field,
+        schema: serde_json::Value,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiEntityType {
+    Email,
+    Ip,
+    CreditCard,
+    Phone,
+}
+
+/// Bounds for numeric expressions. Every bound given must hold, and at
+/// least one is required.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Comparison {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gt: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gte: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lt: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lte: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eq: Option<f64>,
+}
+
+impl Comparison {
+    pub fn is_empty(&self) -> bool {
+        *self == Comparison::default()
+    }
+
+    pub fn check(&self, x: f64) -> bool {
+        self.gt.is_none_or(|b| x > b)
+            && self.gte.is_none_or(|b| x >= b)
+            && self.lt.is_none_or(|b| x < b)
+            && self.lte.is_none_or(|b| x <= b)
+            && self.eq.is_none_or(|b| x == b)
+    }
+
+    /// Only `gt`/`gte`: once a growing value passes, it keeps passing.
+    pub fn lower_bounds_only(&self) -> bool {
+        self.lt.is_none() && self.lte.is_none() && self.eq.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LengthUnit {
+    Bytes,
+    #[default]
+    Chars,
+    /// Whitespace-separated.
+    Words,
+    Lines,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharClass {
+    NonAscii,
+    Alphabetic,
+    Uppercase,
+    Digit,
+    Whitespace,
+    Punctuation,
+    Control,
+}
+
+impl CharClass {
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            CharClass::NonAscii => !c.is_ascii(),
+            CharClass::Alphabetic => c.is_alphabetic(),
+            CharClass::Uppercase => c.is_uppercase(),
+            CharClass::Digit => c.is_numeric(),
+            CharClass::Whitespace => c.is_whitespace(),
+            CharClass::Punctuation => c.is_ascii_punctuation(),
+            CharClass::Control => c.is_control(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EntropyScope {
+    /// The whole field value.
+    Field,
+    #[default]
+    Token,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct ExactOptions {
+    /// ASCII case folding.
+    #[serde(skip_serializing_if = "is_false")]
+    pub case_insensitive: bool,
+    #[serde(skip_serializing_if = "Normalize::is_off")]
+    pub normalize: Normalize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct KeywordOptions {
+    /// ASCII case folding.
+    #[serde(skip_serializing_if = "is_false")]
+    pub case_insensitive: bool,
+    /// Only match keywords not surrounded by letters, digits or `_`.
+    #[serde(skip_serializing_if = "is_false")]
+    pub whole_word: bool,
+    #[serde(skip_serializing_if = "KeywordMatchKind::is_standard")]
+    pub match_kind: KeywordMatchKind,
+    #[serde(skip_serializing_if = "Normalize::is_off")]
+    pub normalize: Normalize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AnomalyOptions {
+    /// Empty means every category but `variation_selector`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<UnicodeCategory>,
+    /// Fewest anomalous characters for a match.
+    pub min_count: usize,
+    /// Delete the matched characters from `output_text` when the rule fires
+    /// (only for `field: text`).
+    #[serde(skip_serializing_if = "is_false")]
+    pub strip: bool,
+}
+
+impl Default for AnomalyOptions {
+    fn default() -> Self {
+        Self {
+            categories: vec![],
+            min_count: 1,
+            strip: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnicodeCategory {
+    /// U+E0000..U+E007F, invisible ASCII look-alikes.
+    Tag,
+    /// Zero-width space/joiners, word joiner, BOM, soft hyphen.
+    ZeroWidth,
+    /// Bidi embeddings, overrides, isolates and marks (e.g. U+202E).
+    Bidi,
+    VariationSelector,
+    /// C0/C1 controls other than tab, newline and carriage return.
+    Control,
+}
+
+impl UnicodeCategory {
+    /// Checked when a rule lists no categories. Variation selectors are
+    /// opt-in: ordinary emoji ("❤️") carry them.
+    pub const DEFAULT: [UnicodeCategory; 4] = [
+        UnicodeCategory::Tag,
+        UnicodeCategory::ZeroWidth,
+        UnicodeCategory::Bidi,
+        UnicodeCategory::Control,
+    ];
+}
+
+/// Mirrors `aho_corasick::MatchKind`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KeywordMatchKind {
+    #[default]
+    Standard,
+    LeftmostFirst,
+    LeftmostLongest,
+}
+
+impl KeywordMatchKind {
+    fn is_standard(&self) -> bool {
+        *self == KeywordMatchKind::Standard
+    }
+}
+
+/// Text normalization applied to both the keywords/value and the field
+/// before matching. Steps run in order: nfkc, strip_punctuation,
+/// collapse_whitespace.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct Normalize {
+    /// Unicode NFKC (folds full-width forms, ligatures, etc.)
+    pub nfkc: bool,
+    /// Runs of whitespace become a single space.
+    pub collapse_whitespace: bool,
+    /// Drop everything that is neither alphanumeric nor whitespace.
+    pub strip_punctuation: bool,
+}
+
+impl Normalize {
+    pub fn is_off(&self) -> bool {
+        !(self.nfkc || self.collapse_whitespace || self.strip_punctuation)
+    }
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
 }
 
 /// What apps call to evaluate a prompt/response.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EvalRequest {
     pub request_id: Option<Uuid>,
-    pub kind: Kind, // prompt|response
+    #[serde(default)]
+    pub kind: Kind, // prompt|response|tool_call|tool_result
+    /// Single text to evaluate; leave empty when sending `messages`.
+    #[serde(default)]
     pub text: String,
     pub tenant: Option<String>,
     pub model: Option<String>,
+
+    /// Role of `text` for role-targeted rules (defaults from `kind`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+
+    /// The call being made, for `kind: tool_call` (or the call that
+    /// produced a `tool_result`). An empty `text` defaults to the arguments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<ToolCall>,
+
+    /// Chat conversation; each message is evaluated on its own.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<Message>,
+
+    /// Overrides the policy's `evaluation.mode` for this request.
+    #[serde(default)]
+    pub mode: Option<EvalMode>,
+
+    /// Caller context (user tier, app id, region, ...) for `metadata.<key>` fields.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, MetadataValue>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl std::fmt::Display for MetadataValue {
+    /// How rules see the value: `true`, `3`, `2.5`, or the string itself.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetadataValue::Bool(b) => write!(f, "{b}"),
+            MetadataValue::Number(n) => write!(f, "{n}"),
+            MetadataValue::String(s) => f.write_str(s),
+        }
+    }
+}
+
+impl EvalRequest {
+    pub fn role(&self) -> Role {
+        self.role.unwrap_or(match self.kind {
+            Kind::Prompt => Role::User,
+            Kind::Response | Kind::ToolCall => Role::Assistant,
+            Kind::ToolResult => Role::Tool,
+        })
+    }
+
+    /// Single-text request for one of `messages`.
+    pub fn for_message(&self, m: &Message) -> EvalRequest {
+        EvalRequest {
+            request_id: self.request_id,
+            kind: m.role.kind(),
+            text: m.content.clone(),
+            tenant: self.tenant.clone(),
+            model: self.model.clone(),
+            role: Some(m.role),
+            tool: None,
+            messages: vec![],
+            mode: self.mode.clone(),
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    /// Tool-call request for one of a message's `tool_calls`.
+    pub fn for_tool_call(&self, call: ToolCall) -> EvalRequest {
+        EvalRequest {
+            request_id: self.request_id,
+            kind: Kind::ToolCall,
+            text: call.arguments_text(),
+            tenant: self.tenant.clone(),
+            model: self.model.clone(),
+            role: Some(Role::Assistant),
+            tool: Some(call),
+            messages: vec![],
+            mode: self.mode.clone(),
+            metadata: self.metadata.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
 pub enum Kind {
+    #[default]
     Prompt,
     Response,
+    /// A tool call the model wants to make; see `EvalRequest::tool`.
+    ToolCall,
+    /// Output of a tool, fed back to the model.
+    ToolResult,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Prompt => "prompt",
+            Kind::Response => "response",
+            Kind::ToolCall => "tool_call",
+            Kind::ToolResult => "tool_result",
+        }
+    }
+}
+
+/// Function call made by the model. `arguments` may be a JSON object or,
+/// as OpenAI sends it, a string holding JSON.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ToolCall {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+impl ToolCall {
+    /// Arguments as JSON, decoding the string form.
+    pub fn args(&self) -> std::borrow::Cow<'_, serde_json::Value> {
+        match &self.arguments {
+            serde_json::Value::String(s) => serde_json::from_str(s)
+                .map(std::borrow::Cow::Owned)
+                .unwrap_or(std::borrow::Cow::Borrowed(&self.arguments)),
+            v => std::borrow::Cow::Borrowed(v),
+        }
+    }
+
+    /// Arguments as JSON text, what `text` rules see for a tool call.
+    pub fn arguments_text(&self) -> String {
+        match &self.arguments {
+            serde_json::Value::String(s) => s.clone(),
+            v => v.to_string(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -344,6 +1453,29 @@ semantic:
         assert_eq!(policy.semantic.cases[0].examples[0].embedding.as_ref().unwrap().len(), 3);
     }
 
+    #[test]
+    fn semantic_examples_round_trip() {
+        let yaml = r#"
+rules: []
+semantic:
+  enabled: true
+  applies_to: prompt
+  action: block
+  threshold: 0.7
+  cases:
+    - id: test
+      examples:
+        - "plain"
+        - {text: "explicit null", embedding: null}
+"#;
+        let policy: PolicyFile = serde_yaml::from_str(yaml).unwrap();
+        let again: PolicyFile =
+            serde_yaml::from_str(&serde_yaml::to_string(&policy).unwrap()).unwrap();
+        let examples = &again.semantic.cases[0].examples;
+        assert_eq!(examples[0].text, "plain");
+        assert!(examples[1].embedding.is_none());
+    }
+
     #[test]
     fn pii_detectors_default_to_false() {
         let detectors = PiiDetectors::default();
@@ -402,7 +1534,7 @@ values:
 "#;
         let expr: MatchExpr = serde_yaml::from_str(yaml).unwrap();
         match expr {
-            MatchExpr::Keywords { field, values } => {
+            MatchExpr::Keywords { field, values, .. } => {
                 assert!(matches!(field, Field::Text));
                 assert_eq!(values.len(), 2);
                 assert_eq!(values[0], "password");
@@ -411,6 +1543,250 @@ values:
         }
     }
 
+    #[test]
+    fn when_nested_groups_deserialization() {
+        let yaml = r#"
+all:
+  - type: regex
+    field: text
+    pattern: "secret"
+  - any:
+      - type: exact
+        field: tenant
+        value: acme
+      - type: exact
+        field: tenant
+        value: globex
+not:
+  type: keywords
+  field: text
+  values: [harmless]
+"#;
+        let when: When = serde_yaml::from_str(yaml).unwrap();
+        assert!(when.any.is_empty());
+        assert_eq!(when.all.len(), 2);
+        assert!(matches!(when.all[0], Condition::Match(MatchExpr::Regex { .. })));
+        match &when.all[1] {
+            Condition::Group(g) => assert_eq!(g.any.len(), 2),
+            _ => panic!("Expected nested group"),
+        }
+        assert!(matches!(when.not.as_deref(), Some(Condition::Match(MatchExpr::Keywords { .. }))));
+    }
+
+    #[test]
+    fn when_rejects_unknown_clause() {
+        let yaml = r#"
+one_of:
+  - type: exact
+    field: text
+    value: x
+"#;
+        assert!(serde_yaml::from_str::<When>(yaml).is_err());
+    }
+
+    #[test]
+    fn match_expr_keyword_options_deserialization() {
+        let yaml = r#"
+type: keywords
+field: text
+values: [pass]
+case_insensitive: true
+whole_word: true
+match_kind: leftmost_longest
+normalize:
+  nfkc: true
+"#;
+        let expr: MatchExpr = serde_yaml::from_str(yaml).unwrap();
+        match &expr {
+            MatchExpr::Keywords { options, .. } => {
+                assert!(options.case_insensitive);
+                assert!(options.whole_word);
+                assert_eq!(options.match_kind, KeywordMatchKind::LeftmostLongest);
+                assert!(options.normalize.nfkc);
+                assert!(!options.normalize.strip_punctuation);
+            }
+            _ => panic!("Expected Keywords"),
+        }
+
+        // defaults stay out of the persisted YAML
+        let plain: MatchExpr = serde_yaml::from_str("{type: keywords, field: text, values: [x]}").unwrap();
+        let yaml = serde_yaml::to_string(&plain).unwrap();
+        assert!(!yaml.contains("whole_word"));
+        assert!(!yaml.contains("normalize"));
+    }
+
+    #[test]
+    fn match_expr_unicode_anomaly_deserialization() {
+        let yaml = r#"
+type: unicode_anomaly
+field: text
+categories: [tag, bidi]
+strip: true
+"#;
+        let expr: MatchExpr = serde_yaml::from_str(yaml).unwrap();
+        match &expr {
+            MatchExpr::UnicodeAnomaly { options, .. } => {
+                assert_eq!(options.categories, vec![UnicodeCategory::Tag, UnicodeCategory::Bidi]);
+                assert_eq!(options.min_count, 1);
+                assert!(options.strip);
+            }
+            _ => panic!("Expected UnicodeAnomaly"),
+        }
+        assert!(serde_yaml::from_str::<MatchExpr>("{type: unicode_anomaly, field: text, categories: [emoji]}").is_err());
+    }
+
+    #[test]
+    fn match_expr_numeric_deserialization() {
+        let yaml = r#"
+- {type: length, field: text, gt: 20000}
+- {type: char_ratio, field: text, class: non_ascii, gt: 0.4}
+- {type: entropy, field: text, scope: field, gte: 4.5}
+- {type: regex_count, field: text, pattern: "https?://", gte: 5, lt: 100}
+"#;
+        let exprs: Vec<MatchExpr> = serde_yaml::from_str(yaml).unwrap();
+        match &exprs[0] {
+            MatchExpr::Length { unit, cmp, .. } => {
+                assert_eq!(*unit, LengthUnit::Chars);
+                assert_eq!(cmp.gt, Some(20000.0));
+            }
+            _ => panic!("Expected Length"),
+        }
+        assert!(matches!(&exprs[1], MatchExpr::CharRatio { class: CharClass::NonAscii, .. }));
+        assert!(matches!(&exprs[2], MatchExpr::Entropy { scope: EntropyScope::Field, min_length: None, .. }));
+        match &exprs[3] {
+            MatchExpr::RegexCount { cmp, .. } => {
+                assert!(cmp.check(5.0) && cmp.check(99.0));
+                assert!(!cmp.check(4.0) && !cmp.check(100.0));
+            }
+            _ => panic!("Expected RegexCount"),
+        }
+    }
+
+    #[test]
+    fn field_paths_round_trip() {
+        for path in ["text", "normalized_text", "request_id", "kind", "metadata.user_tier"] {
+            let field: Field = serde_yaml::from_str(path).unwrap();
+            assert_eq!(serde_yaml::to_string(&field).unwrap().trim(), path);
+        }
+        assert_eq!(
+            serde_yaml::from_str::<Field>("metadata.app.id").unwrap(),
+            Field::Metadata("app.id".to_string())
+        );
+        assert!(serde_yaml::from_str::<Field>("metadata.").is_err());
+        assert!(serde_yaml::from_str::<Field>("user").is_err());
+    }
+
+    #[test]
+    fn eval_request_metadata_deserialization() {
+        let json = r#"{"kind":"prompt","text":"hi","metadata":{"user_tier":"free","age":42,"beta":true}}"#;
+        let req: EvalRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(req.metadata["user_tier"].to_string(), "free");
+        assert_eq!(req.metadata["age"].to_string(), "42");
+        assert_eq!(req.metadata["beta"], MetadataValue::Bool(true));
+    }
+
+    #[test]
+    fn applies_to_roles_and_messages() {
+        let roles: AppliesTo = serde_yaml::from_str("[user, tool]").unwrap();
+        assert_eq!(roles, AppliesTo::Roles(vec![Role::User, Role::Tool]));
+        assert_eq!(serde_yaml::to_string(&roles).unwrap(), "- user\n- tool\n");
+        assert!(roles.covers(&Kind::Prompt, Role::Tool));
+        assert!(!roles.covers(&Kind::Prompt, Role::System));
+        assert!(AppliesTo::Response.covers(&Kind::Response, Role::Assistant));
+        assert!(serde_yaml::from_str::<AppliesTo>("[robot]").is_err());
+
+        let json = r#"{"messages": [
+            {"role": "system", "content": "be brief"},
+            {"role": "assistant", "content": "hi"}
+        ]}"#;
+        let req: EvalRequest = serde_json::from_str(json).unwrap();
+        assert!(req.text.is_empty());
+        let reply = req.for_message(&req.messages[1]);
+        assert!(matches!(reply.kind, Kind::Response));
+        assert_eq!(reply.role(), Role::Assistant);
+        assert_eq!(reply.text, "hi");
+    }
+
+    #[test]
+    fn batch_results_serialize_flat() {
+        let id = Uuid::nil();
+        let err = serde_json::to_value(BatchResult::Err(BatchError {
+            request_id: id,
+            status: 413,
+            error: "text exceeds max_bytes policy".to_string(),
+        }))
+        .unwrap();
+        assert_eq!(err["status"], 413);
+        assert!(err.get("action").is_none());
+
+        let ok = r#"{"request_id": "00000000-0000-0000-0000-000000000000", "action": "allow", "matched_rule": null, "reason": null}"#;
+        let parsed: BatchResult = serde_json::from_str(ok).unwrap();
+        assert!(matches!(parsed, BatchResult::Ok(r) if r.action == Action::Allow));
+    }
+
+    #[test]
+    fn tool_call_requests_and_fields() {
+        let json = r#"{"kind": "tool_call", "tool": {"name": "send_email", "arguments": "{\"to\": \"a@b.co\"}"}}"#;
+        let req: EvalRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(req.kind, Kind::ToolCall);
+        assert_eq!(req.role(), Role::Assistant);
+        let call = req.tool.as_ref().unwrap();
+        assert_eq!(call.args()["to"], "a@b.co");
+        assert_eq!(call.arguments_text(), r#"{"to": "a@b.co"}"#);
+
+        for path in ["tool_name", "tool_args", "tool_args.to", "tool_args..email", "tool_args.cc[*]"] {
+            let field: Field = serde_yaml::from_str(path).unwrap();
+            assert_eq!(serde_yaml::to_string(&field).unwrap().trim(), path);
+        }
+        assert!(serde_yaml::from_str::<Field>("tool_args.[").is_err());
+
+        let applies: AppliesTo = serde_yaml::from_str("tool_call").unwrap();
+        assert!(applies.covers(&Kind::ToolCall, Role::Assistant));
+        // tool kinds are opt-in
+        assert!(!AppliesTo::Response.covers(&Kind::ToolCall, Role::Assistant));
+        assert!(!AppliesTo::Prompt.covers(&Kind::ToolResult, Role::Tool));
+        assert!(!AppliesTo::Both.covers(&Kind::ToolCall, Role::Assistant));
+        assert!(AppliesTo::Roles(vec![Role::Tool]).covers(&Kind::ToolResult, Role::Tool));
+    }
+
+    #[test]
+    fn message_tool_calls_deserialization() {
+        let json = r#"{"role": "assistant", "content": null, "tool_calls": [
+            {"id": "call_1", "type": "function", "function": {"name": "delete_user", "arguments": "{\"id\": 7}"}}
+        ]}"#;
+        let m: Message = serde_json::from_str(json).unwrap();
+        assert!(m.content.is_empty());
+        let call = m.tool_calls[0].to_tool_call();
+        assert_eq!(call.name, "delete_user");
+        assert_eq!(call.args()["id"], 7);
+        assert!(serde_json::to_string(&m).unwrap().contains(r#""type":"function""#));
+
+        let parts = r#"{"role": "user", "content": [
+            {"type": "text", "text": "describe this"},
+            {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}},
+            {"type": "text", "text": "briefly"}
+        ]}"#;
+        let m: Message = serde_json::from_str(parts).unwrap();
+        assert_eq!(m.content, "describe this\nbriefly");
+    }
+
+    #[test]
+    fn normalization_section_defaults() {
+        let policy: PolicyFile = serde_yaml::from_str("rules: []").unwrap();
+        assert!(!policy.normalization.enabled);
+
+        let yaml = r#"
+rules: []
+normalization:
+  enabled: true
+  leetspeak: false
+"#;
+        let policy: PolicyFile = serde_yaml::from_str(yaml).unwrap();
+        assert!(policy.normalization.enabled);
+        assert!(!policy.normalization.leetspeak);
+        assert!(policy.normalization.homoglyphs);
+    }
+
     #[test]
     fn eval_request_deserialization() {
         let json = r#"
@@ -423,6 +1799,21 @@ values:
         assert!(matches!(req.kind, Kind::Prompt));
         assert_eq!(req.text, "test input");
         assert!(req.request_id.is_none());
+        assert!(req.mode.is_none());
+    }
+
+    #[test]
+    fn evaluation_mode_defaults_to_first_match() {
+        let policy: PolicyFile = serde_yaml::from_str("rules: []").unwrap();
+        assert_eq!(policy.evaluation.mode, EvalMode::FirstMatch);
+
+        let yaml = r#"
+rules: []
+evaluation:
+  mode: all_matches
+"#;
+        let policy: PolicyFile = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(policy.evaluation.mode, EvalMode::AllMatches);
     }
 
     #[test]
@@ -434,10 +1825,33 @@ values:
             reason: Some("matched pattern".to_string()),
             output_text: None,
             pii: None,
+            flags: vec![],
+            warnings: vec![],
+            evidence: vec![],
+            matches: None,
+            decoded: None,
+            message: None,
+            output_messages: None,
+            schema_violations: vec![],
         };
         
         let json = serde_json::to_string(&resp).unwrap();
         assert!(json.contains("block"));
         assert!(json.contains("test-rule"));
+        assert!(!json.contains("flags"));
+    }
+
+    #[test]
+    fn extended_action_serialization() {
+        let action: Action = serde_yaml::from_str("require_review").unwrap();
+        assert_eq!(action, Action::RequireReview);
+
+        let yaml = serde_yaml::to_string(&Action::Redact).unwrap();
+        assert!(yaml.contains("redact"));
+
+        assert!(Action::Block.precedence() > Action::RequireReview.precedence());
+        assert!(Action::Warn.precedence() > Action::Flag.precedence());
+        assert!(!Action::Flag.is_terminal());
+        assert!(Action::RequireReview.is_terminal());
     }
 }
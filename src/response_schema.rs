@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Context};
+
+use crate::policy::{Action, EvalRequest, Kind, ResponseSchema, SchemaSelector, SchemaViolation};
+
+/// Violations reported per response; a bad array can otherwise produce one per item.
+const MAX_VIOLATIONS: usize = 50;
+
+#[derive(Debug, Clone)]
+pub struct CompiledResponseSchema {
+    pub id: String,
+    pub selector: SchemaSelector,
+    pub action: Action,
+    pub description: Option<String>,
+    pub validator: jsonschema::Validator,
+}
+
+/// Compile `response_schemas` (policy order is kept: first selector match wins).
+pub fn compile_response_schemas(
+    schemas: &[ResponseSchema],
+) -> anyhow::Result<Vec<CompiledResponseSchema>> {
+    let mut seen = HashSet::new();
+    let mut compiled = Vec::with_capacity(schemas.len());
+    for s in schemas {
+        if !seen.insert(s.id.as_str()) {
+            bail!("duplicate response schema id: {}", s.id);
+        }
+        if matches!(s.action, Action::Allow | Action::Redact) {
+            bail!(
+                "response schema {}: action must be block, require_review, flag or warn",
+                s.id
+            );
+        }
+        let validator = jsonschema::validator_for(&s.schema)
+            .map_err(|e| anyhow::anyhow!("{e}"))
+            .with_context(|| format!("response schema {}: invalid JSON Schema", s.id))?;
+
+        compiled.push(CompiledResponseSchema {
+            id: s.id.clone(),
+            selector: s.selector.clone(),
+            action: s.action.clone(),
+            description: s.description.clone(),
+            validator,
+        });
+    }
+    Ok(compiled)
+}
+
+/// Checks a response against the first schema whose selector matches.
+/// Returns the schema and its violations if the text is not valid JSON or
+/// does not satisfy it.
+pub fn evaluate<'a>(
+    compiled: &'a [CompiledResponseSchema],
+    req: &EvalRequest,
+    text: &str,
+) -> Option<(&'a CompiledResponseSchema, Vec<SchemaViolation>)> {
+    if req.kind != Kind::Response {
+        return None;
+    }
+    let schema = compiled.iter().find(|s| s.selector.matches(req))?;
+
+    let violation = |path: String, error: String| SchemaViolation {
+        schema: schema.id.clone(),
+        path,
+        error,
+        message: None,
+    };
+
+    let violations: Vec<SchemaViolation> = match serde_json::from_str(text) {
+        Ok(doc) => schema
+            .validator
+            .iter_errors(&doc)
+            .take(MAX_VIOLATIONS)
+            .map(|e| violation(e.instance_path().to_string(), e.to_string()))
+            .collect(),
+        Err(e) => vec![violation(
+            String::new(),
+            format!("response is not valid JSON: {e}"),
+        )],
+    };
+
+    (!violations.is_empty()).then_some((schema, violations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schemas(yaml: &str) -> Vec<CompiledResponseSchema> {
+        let parsed: Vec<ResponseSchema> = serde_yaml::from_str(yaml).unwrap();
+        compile_response_schemas(&parsed).unwrap()
+    }
+
+    fn response(model: &str, text: &str) -> EvalRequest {
+        serde_json::from_value(serde_json::json!({
+            "kind": "response",
+            "model": model,
+            "text": text,
+            "metadata": {"feature": "extract"},
+        }))
+        .unwrap()
+    }
+
+    const ORDER_SCHEMA: &str = r#"
+- id: order
+  selector:
+    model: "gpt-4o*"
+    metadata: {feature: extract}
+  schema:
+    type: object
+    required: [id, total]
+    properties:
+      id: {type: string}
+      total: {type: number, minimum: 0}
+- id: fallback
+  action: flag
+  schema: {type: object}
+"#;
+
+    #[test]
+    fn selects_first_matching_schema() {
+        let compiled = schemas(ORDER_SCHEMA);
+
+        let ok = response("gpt-4o-mini", r#"{"id": "A1", "total": 3.5}"#);
+        assert!(evaluate(&compiled, &ok, &ok.text).is_none());
+
+        let bad = response("gpt-4o", r#"{"id": 7, "total": -1}"#);
+        let (schema, violations) = evaluate(&compiled, &bad, &bad.text).unwrap();
+        assert_eq!(schema.id, "order");
+        assert_eq!(schema.action, Action::Block);
+        let mut paths: Vec<&str> = violations.iter().map(|v| v.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/id", "/total"]);
+
+        // other models fall through to the catch-all
+        let other = response("claude", "[1, 2]");
+        let (schema, violations) = evaluate(&compiled, &other, &other.text).unwrap();
+        assert_eq!(schema.id, "fallback");
+        assert_eq!(violations[0].path, "");
+    }
+
+    #[test]
+    fn invalid_json_and_prompts() {
+        let compiled = schemas(ORDER_SCHEMA);
+
+        let req = response("gpt-4o", "Sure! Here is the order: {");
+        let (_, violations) = evaluate(&compiled, &req, &req.text).unwrap();
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0]
+            .error
+            .starts_with("response is not valid JSON"));
+
+        let mut prompt = req.clone();
+        prompt.kind = Kind::Prompt;
+        assert!(evaluate(&compiled, &prompt, &prompt.text).is_none());
+    }
+
+    #[test]
+    fn compile_rejects_bad_schemas() {
+        let bad: Vec<ResponseSchema> =
+            serde_yaml::from_str("- {id: a, schema: {type: 12}}").unwrap();
+        assert!(compile_response_schemas(&bad).is_err());
+
+        let redact: Vec<ResponseSchema> =
+            serde_yaml::from_str("- {id: a, action: redact, schema: {}}").unwrap();
+        assert!(compile_response_schemas(&redact).is_err());
+
+        let dup: Vec<ResponseSchema> =
+            serde_yaml::from_str("[{id: a, schema: {}}, {id: a, schema: {}}]").unwrap();
+        assert!(compile_response_schemas(&dup).is_err());
+    }
+}
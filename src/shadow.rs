@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::info;
+
+use crate::dry_run::{changes, verdict, Change};
+use crate::policy::{BatchResult, PolicyFile};
+use crate::store::PolicySnapshot;
+
+/// Shadow comparisons running at once; requests beyond that skip the
+/// shadow policy rather than queue up blocking work.
+pub(crate) const MAX_IN_FLIGHT: usize = 64;
+
+/// A candidate policy evaluated next to the active one on live `/v1/eval`
+/// traffic. Its verdicts are never returned; disagreements are logged
+/// (target `shadow`) and counted.
+pub struct ShadowPolicy {
+    pub(crate) policy: PolicyFile,
+    pub(crate) snapshot: PolicySnapshot,
+    slots: Arc<Semaphore>,
+    metrics: Mutex<ShadowMetrics>,
+}
+
+/// Counters since the shadow policy was set.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ShadowMetrics {
+    pub evaluated: u64,
+    pub disagreements: u64,
+    /// Disagreements per kind of change (an item can count for several).
+    pub changes: BTreeMap<Change, u64>,
+    /// Verdict changes, e.g. `allow -> block`.
+    pub transitions: BTreeMap<String, u64>,
+    /// Requests not compared because `MAX_IN_FLIGHT` comparisons were running.
+    pub dropped: u64,
+}
+
+impl ShadowPolicy {
+    pub(crate) fn compile(policy: PolicyFile) -> anyhow::Result<Self> {
+        Ok(Self {
+            snapshot: PolicySnapshot::compile(&policy)?,
+            policy,
+            slots: Arc::new(Semaphore::new(MAX_IN_FLIGHT)),
+            metrics: Mutex::default(),
+        })
+    }
+
+    /// A slot for one comparison, held until it is recorded. `None` (and
+    /// the request counted as dropped) when every slot is taken.
+    pub(crate) fn try_reserve(&self) -> Option<OwnedSemaphorePermit> {
+        let permit = self.slots.clone().try_acquire_owned().ok();
+        if permit.is_none() {
+            self.metrics
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .dropped += 1;
+        }
+        permit
+    }
+
+    /// Compares the shadow verdict with the active one for the same request.
+    pub(crate) fn record(&self, active: &BatchResult, shadow: &BatchResult) -> Vec<Change> {
+        let changes = changes(active, shadow);
+        let (from, to) = (verdict(active), verdict(shadow));
+
+        {
+            let mut m = self.metrics.lock().unwrap_or_else(|e| e.into_inner());
+            m.evaluated += 1;
+            if !changes.is_empty() {
+                m.disagreements += 1;
+                for c in &changes {
+                    *m.changes.entry(*c).or_insert(0) += 1;
+                }
+                if from != to {
+                    *m.transitions.entry(format!("{from} -> {to}")).or_insert(0) += 1;
+                }
+            }
+        }
+
+        if !changes.is_empty() {
+            info!(
+                target: "shadow",
+                request_id = %request_id(active),
+                active = %from,
+                shadow = %to,
+                active_rule = ?matched_rule(active),
+                shadow_rule = ?matched_rule(shadow),
+                changes = ?changes,
+                "shadow policy disagrees"
+            );
+        }
+        changes
+    }
+
+    pub(crate) fn metrics(&self) -> ShadowMetrics {
+        self.metrics
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+fn request_id(r: &BatchResult) -> uuid::Uuid {
+    match r {
+        BatchResult::Ok(r) => r.request_id,
+        BatchResult::Err(e) => e.request_id,
+    }
+}
+
+fn matched_rule(r: &BatchResult) -> Option<&str> {
+    match r {
+        BatchResult::Ok(r) => r.matched_rule.as_deref(),
+        BatchResult::Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::batch_result;
+
+    #[test]
+    fn counts_disagreements() {
+        let shadow = ShadowPolicy::compile(serde_yaml::from_str("rules: []").unwrap()).unwrap();
+        let allow = batch_result(r#"{"action": "allow", "matched_rule": null, "reason": null}"#);
+        let block = batch_result(r#"{"action": "block", "matched_rule": "r", "reason": null}"#);
+
+        assert!(shadow.record(&allow, &allow).is_empty());
+        assert_eq!(
+            shadow.record(&allow, &block),
+            vec![Change::Action, Change::MatchedRule]
+        );
+        shadow.record(&allow, &block);
+
+        let m = shadow.metrics();
+        assert_eq!(m.evaluated, 3);
+        assert_eq!(m.disagreements, 2);
+        assert_eq!(m.changes[&Change::Action], 2);
+        assert_eq!(m.transitions["allow -> block"], 2);
+    }
+    #[test]
+    fn drops_comparisons_over_the_limit() {
+        let shadow = ShadowPolicy::compile(serde_yaml::from_str("rules: []").unwrap()).unwrap();
+        let held: Vec<_> = (0..MAX_IN_FLIGHT)
+            .map(|_| shadow.try_reserve().unwrap())
+            .collect();
+        assert!(shadow.try_reserve().is_none());
+        assert_eq!(shadow.metrics().dropped, 1);
+
+        drop(held);
+        assert!(shadow.try_reserve().is_some());
+        assert_eq!(shadow.metrics().dropped, 1);
+    }
+}
@@ -1,21 +1,143 @@
 use crate::compile::{compile_rule, CompiledRule};
-use crate::policy::{PiiConfig, PolicyFile, Rule, SemanticConfig};
+use crate::history::{policy_hash, ChangeMeta, History, PolicyVersion, VersionInfo};
+use crate::policy::{
+    DecodingConfig, EvalRequest, EvaluationConfig, NormalizationConfig, PiiConfig, PolicyFile,
+    Rule, StreamingConfig,
+};
+use crate::response_schema::{compile_response_schemas, CompiledResponseSchema};
 use crate::semantic::{compile_semantic, CompiledSemantic};
-use std::{path::PathBuf, sync::Arc};
+use crate::shadow::ShadowPolicy;
+use crate::validate::{check_policy, PolicyError, PolicyErrorKind};
+use arc_swap::{ArcSwap, ArcSwapOption};
+use std::{borrow::Cow, path::PathBuf, sync::Arc};
 use tokio::sync::RwLock;
+use tracing::warn;
 
 #[derive(Clone)]
 pub struct RuleStore {
+    /// Source of truth for writers (admin API); serializes applies.
     inner: Arc<RwLock<Inner>>,
+    /// Compiled form of `inner.policy`, read lock-free on every eval. Only
+    /// replaced while holding the `inner` write lock.
+    snapshot: Arc<ArcSwap<PolicySnapshot>>,
+    /// Candidate evaluated alongside on live traffic; not persisted.
+    shadow: Arc<ArcSwapOption<ShadowPolicy>>,
 }
 
 struct Inner {
     policy_path: PathBuf,
-    rules: Vec<Rule>,
-    compiled: Vec<CompiledRule>,
-    pii: PiiConfig,
-    semantic_cfg: SemanticConfig,
-    semantic: CompiledSemantic,
+    policy: PolicyFile,
+    history: History,
+    /// `policy_hash` of the active policy.
+    hash: String,
+}
+
+/// Policy state an evaluation reads: immutable, shared, and swapped as a
+/// whole so every stage of a request sees the same policy.
+#[derive(Clone)]
+pub struct PolicySnapshot {
+    pub compiled: Vec<CompiledRule>,
+    /// The `compiled` rules streams evaluate mid-stream (`window_safe`);
+    /// the rest only run on the complete text.
+    pub window_rules: Vec<CompiledRule>,
+    pub evaluation: EvaluationConfig,
+    pub normalization: NormalizationConfig,
+    pub decoding: DecodingConfig,
+    pub pii: PiiConfig,
+    pub semantic: CompiledSemantic,
+    pub schemas: Vec<CompiledResponseSchema>,
+    pub streaming: StreamingConfig,
+}
+
+impl PolicySnapshot {
+    /// Fails with `InvalidPolicy` when a rule or response schema does not
+    /// compile.
+    pub fn compile(policy: &PolicyFile) -> anyhow::Result<Self> {
+        Self::compile_unchecked(policy).map_err(|e| {
+            // only on failure: find every error, each with its path
+            let mut errors = check_policy(policy);
+            if errors.is_empty() {
+                errors.push(PolicyError {
+                    kind: PolicyErrorKind::Compile,
+                    rule_id: None,
+                    path: String::new(),
+                    line: None,
+                    column: None,
+                    message: format!("{e:#}"),
+                });
+            }
+            InvalidPolicy { errors }.into()
+        })
+    }
+
+    fn compile_unchecked(policy: &PolicyFile) -> anyhow::Result<Self> {
+        let compiled = compile_all(&policy.rules)?;
+        Ok(Self {
+            window_rules: compiled
+                .iter()
+                .filter(|r| r.when.window_safe())
+                .cloned()
+                .collect(),
+            compiled,
+            evaluation: policy.evaluation.clone(),
+            normalization: policy.normalization.clone(),
+            decoding: policy.decoding.clone(),
+            pii: policy.pii.clone(),
+            semantic: compile_semantic(&policy.semantic),
+            schemas: compile_response_schemas(&policy.response_schemas)?,
+            streaming: policy.streaming.clone(),
+        })
+    }
+
+    /// `evaluation`, with the request's `mode` override applied.
+    pub fn evaluation_for(&self, req: &EvalRequest) -> Cow<'_, EvaluationConfig> {
+        match &req.mode {
+            Some(mode) if *mode != self.evaluation.mode => Cow::Owned(EvaluationConfig {
+                mode: mode.clone(),
+                ..self.evaluation.clone()
+            }),
+            _ => Cow::Borrowed(&self.evaluation),
+        }
+    }
+}
+
+/// Identifies the active policy: its content hash (the ETag on the admin
+/// API) and its version, if it was recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyTag {
+    pub hash: String,
+    pub version: Option<u64>,
+}
+
+impl PolicyTag {
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.hash)
+    }
+}
+
+/// Why `update_policy` could not make an edit.
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyEditError {
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("{0} already exists")]
+    Conflict(String),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// A policy that does not compile, with every error found.
+#[derive(Debug, thiserror::Error)]
+#[error("policy does not compile ({} errors)", .errors.len())]
+pub struct InvalidPolicy {
+    pub errors: Vec<PolicyError>,
+}
+
+/// `apply_policy` expected a policy that is no longer the active one.
+#[derive(Debug, thiserror::Error)]
+#[error("policy has changed since it was read (current ETag {})", .current.etag())]
+pub struct StalePolicy {
+    pub current: PolicyTag,
 }
 
 impl RuleStore {
@@ -25,20 +147,31 @@ impl RuleStore {
             .unwrap_or_else(|_| "rules: []\n".to_string());
 
         let policy: PolicyFile = serde_yaml::from_str(&raw)?;
+        let snapshot = PolicySnapshot::compile(&policy)?;
 
-        let compiled = compile_all(&policy.rules)?;
-        let semantic_cfg = policy.semantic.clone();
-        let semantic = compile_semantic(&semantic_cfg);
+        // policy.yaml edited by hand (or the first start) becomes a new version;
+        // a read-only config directory only costs the history
+        let hash = policy_hash(&policy)?;
+        let mut history = History::open(History::dir_for(&policy_path)).await?;
+        if history.latest().map(|v| &v.hash) != Some(&hash) {
+            let meta = ChangeMeta {
+                author: None,
+                note: Some(format!("loaded from {}", policy_path.display())),
+            };
+            if let Err(e) = history.record(&policy, meta).await {
+                warn!("policy history not recorded: {e:#}");
+            }
+        }
 
         Ok(Self {
             inner: Arc::new(RwLock::new(Inner {
                 policy_path,
-                rules: policy.rules,
-                compiled,
-                pii: policy.pii,
-                semantic_cfg,
-                semantic,
+                policy,
+                history,
+                hash,
             })),
+            snapshot: Arc::new(ArcSwap::from_pointee(snapshot)),
+            shadow: Arc::new(ArcSwapOption::empty()),
         })
     }
 
@@ -48,55 +181,162 @@ impl RuleStore {
 
     /// Returns the currently active full policy (rules + pii + semantic).
     pub async fn get_policy(&self) -> PolicyFile {
+        self.get_policy_tagged().await.0
+    }
+
+    /// Like `get_policy`, plus the policy's tag, read together.
+    pub async fn get_policy_tagged(&self) -> (PolicyFile, PolicyTag) {
         let r = self.inner.read().await;
-        PolicyFile {
-            rules: r.rules.clone(),
-            pii: r.pii.clone(),
-            semantic: r.semantic_cfg.clone(),
-        }
+        (r.policy.clone(), tag_of(&r))
     }
 
     /// Applies a full policy atomically:
     /// - compile/validate first
-    /// - swap state
+    /// - check `expected_hash` (if given) against the active policy
+    /// - record it as a new version
+    /// - swap state (evals in flight keep the snapshot they loaded)
     /// - persist full policy.yaml
-    pub async fn apply_policy(&self, policy: PolicyFile) -> anyhow::Result<()> {
+    pub async fn apply_policy(
+        &self,
+        policy: PolicyFile,
+        meta: ChangeMeta,
+        expected_hash: Option<&str>,
+    ) -> anyhow::Result<VersionInfo> {
         // Compile first — if it fails (bad regex), we don’t mutate state or persist.
-        let compiled = compile_all(&policy.rules)?;
-        let semantic_cfg = policy.semantic.clone();
-        let semantic = compile_semantic(&semantic_cfg);
+        let snapshot = PolicySnapshot::compile(&policy)?;
 
         let mut w = self.inner.write().await;
-        w.rules = policy.rules;
-        w.pii = policy.pii;
-        w.compiled = compiled;
-        w.semantic_cfg = semantic_cfg;
-        w.semantic = semantic;
+        if expected_hash.is_some_and(|h| h != w.hash) {
+            return Err(StalePolicy { current: tag_of(&w) }.into());
+        }
+        let version = w.history.record(&policy, meta).await?;
+        w.hash = version.hash.clone();
+        w.policy = policy;
+        self.snapshot.store(Arc::new(snapshot));
 
-        persist_locked(&w).await
+        persist_locked(&w).await?;
+        Ok(version)
+    }
+
+    /// Applies an edit of the active policy (rule/section endpoints). The
+    /// edit runs on a copy; if another write lands first it is retried on
+    /// the new policy, unless the caller pinned `expected_hash`.
+    pub async fn update_policy<T>(
+        &self,
+        meta: ChangeMeta,
+        expected_hash: Option<&str>,
+        edit: impl Fn(&mut PolicyFile) -> anyhow::Result<T>,
+    ) -> anyhow::Result<(T, VersionInfo)> {
+        loop {
+            let (mut policy, tag) = self.get_policy_tagged().await;
+            if expected_hash.is_some_and(|h| h != tag.hash) {
+                return Err(StalePolicy { current: tag }.into());
+            }
+            let out = edit(&mut policy)?;
+            match self.apply_policy(policy, meta.clone(), Some(&tag.hash)).await {
+                Ok(version) => return Ok((out, version)),
+                Err(e) if expected_hash.is_none() && e.is::<StalePolicy>() => continue,
+                Err(e) => return Err(e),
+            }
+        }
     }
 
     // -------------------------
-    // Snapshots for fast eval
+    // Version history
     // -------------------------
 
-    pub async fn compiled_snapshot(&self) -> Vec<CompiledRule> {
-        self.inner.read().await.compiled.clone()
+    pub async fn list_versions(&self) -> Vec<VersionInfo> {
+        self.inner.read().await.history.list().to_vec()
+    }
+
+    pub async fn get_version(&self, version: u64) -> anyhow::Result<Option<PolicyVersion>> {
+        self.inner.read().await.history.get(version).await
+    }
+
+    /// Re-applies an earlier version (as a new version, through the usual
+    /// compile-validate-swap path). Returns None if there is no such version.
+    pub async fn rollback(
+        &self,
+        version: u64,
+        mut meta: ChangeMeta,
+        expected_hash: Option<&str>,
+    ) -> anyhow::Result<Option<VersionInfo>> {
+        let Some(old) = self.get_version(version).await? else {
+            return Ok(None);
+        };
+        meta.note.get_or_insert_with(|| format!("rollback to version {version}"));
+        self.apply_policy(old.policy, meta, expected_hash)
+            .await
+            .map(Some)
+    }
+
+    // -------------------------
+    // Shadow policy (in memory only)
+    // -------------------------
+
+    pub fn shadow(&self) -> Option<Arc<ShadowPolicy>> {
+        self.shadow.load_full()
+    }
+
+    /// Compiles `policy` and starts shadowing it (fresh metrics).
+    pub fn set_shadow(&self, policy: PolicyFile) -> anyhow::Result<()> {
+        let shadow = ShadowPolicy::compile(policy)?;
+        self.shadow.store(Some(Arc::new(shadow)));
+        Ok(())
+    }
+
+    /// Returns whether there was a shadow policy.
+    pub fn clear_shadow(&self) -> bool {
+        self.shadow.swap(None).is_some()
     }
 
-    pub async fn pii_config(&self) -> PiiConfig {
-        self.inner.read().await.pii.clone()
+    /// Applies the shadow policy as the active one and stops shadowing.
+    /// Returns the new version, or `None` if there is no shadow policy.
+    pub async fn promote_shadow(
+        &self,
+        mut meta: ChangeMeta,
+    ) -> anyhow::Result<Option<VersionInfo>> {
+        let Some(shadow) = self.shadow() else {
+            return Ok(None);
+        };
+        meta.note.get_or_insert_with(|| "promoted shadow policy".to_string());
+        let version = self.apply_policy(shadow.policy.clone(), meta, None).await?;
+
+        // unless it was replaced meanwhile
+        self.shadow.compare_and_swap(&Some(shadow), None);
+        Ok(Some(version))
     }
 
-    pub async fn semantic_snapshot(&self) -> CompiledSemantic {
-        self.inner.read().await.semantic.clone()
+    // -------------------------
+    // Snapshot for fast eval
+    // -------------------------
+
+    /// The active policy, compiled. One atomic load; no lock, no copy.
+    pub fn snapshot(&self) -> Arc<PolicySnapshot> {
+        self.snapshot.load_full()
     }
 }
 
-fn compile_all(rules: &[Rule]) -> anyhow::Result<Vec<CompiledRule>> {
+fn tag_of(r: &Inner) -> PolicyTag {
+    PolicyTag {
+        hash: r.hash.clone(),
+        // unless recording it failed
+        version: r
+            .history
+            .latest()
+            .filter(|v| v.hash == r.hash)
+            .map(|v| v.version),
+    }
+}
+
+pub(crate) fn compile_all(rules: &[Rule]) -> anyhow::Result<Vec<CompiledRule>> {
     let mut compiled = Vec::with_capacity(rules.len());
     for r in rules {
-        compiled.push(compile_rule(r)?);
+        // disabled rules are still validated
+        let c = compile_rule(r)?;
+        if r.enabled {
+            compiled.push(c);
+        }
     }
 
     // priority ascending; then id for deterministic tie-breaker
@@ -106,12 +346,7 @@ fn compile_all(rules: &[Rule]) -> anyhow::Result<Vec<CompiledRule>> {
 
 async fn persist_locked(w: &Inner) -> anyhow::Result<()> {
     // Persist rules + pii + semantic (policy.yaml is source of truth)
-    let policy = PolicyFile {
-        rules: w.rules.clone(),
-        pii: w.pii.clone(),
-        semantic: w.semantic_cfg.clone(),
-    };
-    let yaml = serde_yaml::to_string(&policy)?;
+    let yaml = serde_yaml::to_string(&w.policy)?;
 
     tokio::fs::create_dir_all(w.policy_path.parent().unwrap_or(std::path::Path::new("./"))).await?;
     tokio::fs::write(&w.policy_path, yaml).await?;
@@ -132,12 +367,13 @@ mod tests {
                 applies_to: AppliesTo::Prompt,
                 action: Action::Block,
                 priority: 10,
-                when: When {
-                    any: vec![MatchExpr::Exact {
-                        field: Field::Text,
-                        value: "test".to_string(),
-                    }],
-                },
+                enabled: true,
+                message: None,
+                when: When::any_of(vec![MatchExpr::Exact {
+                    field: Field::Text,
+                    value: "test".to_string(),
+                    options: Default::default(),
+                }]),
             }],
             pii: PiiConfig {
                 enabled: true,
@@ -149,6 +385,11 @@ mod tests {
                 include_findings: false,
             },
             semantic: SemanticConfig::default(),
+            evaluation: EvaluationConfig::default(),
+            normalization: NormalizationConfig::default(),
+            decoding: DecodingConfig::default(),
+            response_schemas: vec![],
+            streaming: Default::default(),
         }
     }
 
@@ -185,7 +426,7 @@ mod tests {
         new_policy.rules[0].id = "updated-rule".to_string();
         new_policy.pii.enabled = false;
         
-        store.apply_policy(new_policy).await.unwrap();
+        store.apply_policy(new_policy, ChangeMeta::default(), None).await.unwrap();
         
         // Verify updated
         let retrieved = store.get_policy().await;
@@ -210,25 +451,52 @@ mod tests {
             applies_to: AppliesTo::Prompt,
             action: Action::Block,
             priority: 5, // Lower than first rule (10)
-            when: When {
-                any: vec![MatchExpr::Exact {
-                    field: Field::Text,
-                    value: "urgent".to_string(),
-                }],
-            },
+            enabled: true,
+            message: None,
+            when: When::any_of(vec![MatchExpr::Exact {
+                field: Field::Text,
+                value: "urgent".to_string(),
+                options: Default::default(),
+            }]),
         });
         
         let yaml = serde_yaml::to_string(&policy).unwrap();
         tokio::fs::write(&policy_path, yaml).await.unwrap();
 
         let store = RuleStore::load(policy_path).await.unwrap();
-        let compiled = store.compiled_snapshot().await;
+        let compiled = &store.snapshot().compiled;
         
         assert_eq!(compiled.len(), 2);
         assert_eq!(compiled[0].id, "high-priority"); // Priority 5 comes first
         assert_eq!(compiled[1].id, "test-rule");     // Priority 10 comes second
     }
 
+    #[tokio::test]
+    async fn apply_swaps_snapshot_without_touching_loaded_ones() {
+        let temp_dir = TempDir::new().unwrap();
+        let policy_path = temp_dir.path().join("policy.yaml");
+
+        let policy = create_test_policy().await;
+        let yaml = serde_yaml::to_string(&policy).unwrap();
+        tokio::fs::write(&policy_path, yaml).await.unwrap();
+
+        let store = RuleStore::load(policy_path).await.unwrap();
+        let before = store.snapshot();
+        assert!(Arc::ptr_eq(&before, &store.snapshot()));
+
+        let mut new_policy = policy.clone();
+        new_policy.rules[0].id = "updated-rule".to_string();
+        new_policy.pii.enabled = false;
+        store.apply_policy(new_policy, ChangeMeta::default(), None).await.unwrap();
+
+        // an eval in flight keeps a consistent view of the old policy
+        assert_eq!(before.compiled[0].id, "test-rule");
+        assert!(before.pii.enabled);
+        let after = store.snapshot();
+        assert_eq!(after.compiled[0].id, "updated-rule");
+        assert!(!after.pii.enabled);
+    }
+
     #[tokio::test]
     async fn pii_config_snapshot() {
         let temp_dir = TempDir::new().unwrap();
@@ -239,7 +507,7 @@ mod tests {
         tokio::fs::write(&policy_path, yaml).await.unwrap();
 
         let store = RuleStore::load(policy_path).await.unwrap();
-        let pii = store.pii_config().await;
+        let pii = &store.snapshot().pii;
         
         assert!(pii.enabled);
         assert_eq!(pii.redaction_token, "REDACTED");
@@ -255,8 +523,144 @@ mod tests {
         tokio::fs::write(&policy_path, yaml).await.unwrap();
 
         let store = RuleStore::load(policy_path).await.unwrap();
-        let semantic = store.semantic_snapshot().await;
+        let semantic = &store.snapshot().semantic;
         
         assert!(!semantic.enabled); // Default is disabled
     }
+
+    #[tokio::test]
+    async fn promote_shadow_applies_and_clears() {
+        let temp_dir = TempDir::new().unwrap();
+        let policy_path = temp_dir.path().join("policy.yaml");
+
+        let policy = create_test_policy().await;
+        let yaml = serde_yaml::to_string(&policy).unwrap();
+        tokio::fs::write(&policy_path, yaml).await.unwrap();
+
+        let store = RuleStore::load(policy_path).await.unwrap();
+        assert!(store
+            .promote_shadow(ChangeMeta::default())
+            .await
+            .unwrap()
+            .is_none());
+
+        let mut candidate = create_test_policy().await;
+        candidate.rules[0].id = "shadow-rule".to_string();
+        store.set_shadow(candidate).unwrap();
+
+        // shadowing leaves the active policy alone
+        assert_eq!(store.get_policy().await.rules[0].id, "test-rule");
+        assert_eq!(store.shadow().unwrap().policy.rules[0].id, "shadow-rule");
+
+        let meta = ChangeMeta {
+            author: Some("alice".to_string()),
+            note: None,
+        };
+        let version = store.promote_shadow(meta).await.unwrap().unwrap();
+        assert_eq!(version.author.as_deref(), Some("alice"));
+        assert_eq!(version.note.as_deref(), Some("promoted shadow policy"));
+        assert_eq!(store.get_policy().await.rules[0].id, "shadow-rule");
+        assert!(store.shadow().is_none());
+        assert!(!store.clear_shadow());
+    }
+
+    #[tokio::test]
+    async fn rollback_reapplies_as_new_version() {
+        let temp_dir = TempDir::new().unwrap();
+        let policy_path = temp_dir.path().join("policy.yaml");
+
+        let policy = create_test_policy().await;
+        let yaml = serde_yaml::to_string(&policy).unwrap();
+        tokio::fs::write(&policy_path, yaml).await.unwrap();
+
+        let store = RuleStore::load(policy_path.clone()).await.unwrap();
+        let mut updated = create_test_policy().await;
+        updated.rules[0].id = "updated-rule".to_string();
+        let meta = ChangeMeta {
+            author: Some("bob".to_string()),
+            note: None,
+        };
+        assert_eq!(store.apply_policy(updated, meta, None).await.unwrap().version, 2);
+
+        let v3 = store.rollback(1, ChangeMeta::default(), None).await.unwrap().unwrap();
+        assert_eq!(v3.version, 3);
+        assert_eq!(v3.note.as_deref(), Some("rollback to version 1"));
+        assert_eq!(v3.hash, store.list_versions().await[0].hash);
+        assert_eq!(store.get_policy().await.rules[0].id, "test-rule");
+        assert!(store.rollback(9, ChangeMeta::default(), None).await.unwrap().is_none());
+
+        // history survives a restart, unchanged policy adds no version
+        let reloaded = RuleStore::load(policy_path).await.unwrap();
+        let versions = reloaded.list_versions().await;
+        assert_eq!(versions.len(), 3);
+        assert_eq!(versions[1].author.as_deref(), Some("bob"));
+    }
+
+    #[tokio::test]
+    async fn apply_policy_rejects_stale_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        let policy_path = temp_dir.path().join("policy.yaml");
+
+        let policy = create_test_policy().await;
+        let yaml = serde_yaml::to_string(&policy).unwrap();
+        tokio::fs::write(&policy_path, yaml).await.unwrap();
+
+        let store = RuleStore::load(policy_path).await.unwrap();
+        let (_, read) = store.get_policy_tagged().await;
+        assert_eq!(read.version, Some(1));
+
+        // first writer wins
+        let mut first = create_test_policy().await;
+        first.rules[0].id = "first".to_string();
+        store
+            .apply_policy(first, ChangeMeta::default(), Some(&read.hash))
+            .await
+            .unwrap();
+
+        let mut second = create_test_policy().await;
+        second.rules[0].id = "second".to_string();
+        let err = store
+            .apply_policy(second, ChangeMeta::default(), Some(&read.hash))
+            .await
+            .unwrap_err();
+        let stale = err.downcast_ref::<StalePolicy>().unwrap();
+
+        let (active, current) = store.get_policy_tagged().await;
+        assert_eq!(stale.current, current);
+        assert_eq!(current.version, Some(2));
+        assert_eq!(active.rules[0].id, "first");
+    }
+
+    #[tokio::test]
+    async fn update_policy_edits_and_skips_disabled_rules() {
+        let temp_dir = TempDir::new().unwrap();
+        let policy_path = temp_dir.path().join("policy.yaml");
+
+        let policy = create_test_policy().await;
+        let yaml = serde_yaml::to_string(&policy).unwrap();
+        tokio::fs::write(&policy_path, yaml).await.unwrap();
+
+        let store = RuleStore::load(policy_path).await.unwrap();
+        let (found, v) = store
+            .update_policy(ChangeMeta::default(), None, |p| {
+                let rule = p.rules.iter_mut().find(|r| r.id == "test-rule");
+                Ok(rule.map(|r| r.enabled = false).is_some())
+            })
+            .await
+            .unwrap();
+        assert!(found);
+        assert_eq!(v.version, 2);
+        assert!(!store.get_policy().await.rules[0].enabled);
+        assert!(store.snapshot().compiled.is_empty());
+
+        // a failed edit changes nothing
+        let err = store
+            .update_policy(ChangeMeta::default(), None, |_| -> anyhow::Result<()> {
+                Err(PolicyEditError::NotFound("rule x".into()).into())
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(PolicyEditError::NotFound(_))));
+        assert_eq!(store.list_versions().await.len(), 2);
+    }
 }
@@ -0,0 +1,505 @@
+use std::{ops::Range, sync::Arc};
+
+use axum::{
+    extract::{
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{detect_pii, eval_response, evaluate_request_within, pii_should_run, AppState},
+    evaluator::{evaluate_stage1, redact_spans, EvalInput, Stage1Outcome},
+    pii_regex::PiiRegexDetector,
+    policy::{Action, EvalRequest, EvalResponse, Field},
+    store::PolicySnapshot,
+};
+
+/// What a chunk (or the end of the stream) produced.
+#[derive(Debug)]
+pub enum StreamStep {
+    /// Text that is now safe to pass on (may be empty).
+    Emit(String),
+    /// A `block` / `require_review` rule fired; nothing more is emitted.
+    Blocked(Box<Stage1Outcome>),
+}
+
+/// Evaluates text as it streams in. Stage 1 rules and PII detection run over
+/// the new text plus `holdback_bytes` of look-behind; the trailing
+/// `holdback_bytes` (and any redaction reaching into them) are held back
+/// until the next chunk, so a match split across chunks is redacted whole.
+/// Only rules that hold on part of the text (`window_rules`) run here;
+/// the others are left to the final verdict on the complete text.
+pub struct StreamEvaluator {
+    snap: Arc<PolicySnapshot>,
+    detector: PiiRegexDetector,
+    /// `text` is everything received so far.
+    req: EvalRequest,
+    /// Bytes of `req.text` already emitted.
+    emitted: usize,
+}
+
+impl StreamEvaluator {
+    pub fn new(snap: Arc<PolicySnapshot>, detector: PiiRegexDetector, req: EvalRequest) -> Self {
+        Self {
+            snap,
+            detector,
+            req,
+            emitted: 0,
+        }
+    }
+
+    pub fn push(&mut self, chunk: &str) -> anyhow::Result<StreamStep> {
+        if self.req.text.len() + chunk.len() > self.snap.streaming.max_bytes {
+            anyhow::bail!("stream exceeds streaming.max_bytes policy");
+        }
+        self.req.text.push_str(chunk);
+        Ok(self.advance(false))
+    }
+
+    /// Flushes everything still held back.
+    pub fn finish(&mut self) -> StreamStep {
+        self.advance(true)
+    }
+
+    /// The complete request, for the final verdict.
+    pub fn into_request(self) -> (Arc<PolicySnapshot>, EvalRequest) {
+        (self.snap, self.req)
+    }
+
+    fn advance(&mut self, end: bool) -> StreamStep {
+        // out of `req` while the window request is built from it, so only
+        // the window is copied per chunk
+        let text = std::mem::take(&mut self.req.text);
+        let step = self.advance_over(&text, end);
+        self.req.text = text;
+        step
+    }
+
+    fn advance_over(&mut self, text: &str, end: bool) -> StreamStep {
+        let holdback = self.snap.streaming.holdback_bytes;
+
+        // look-behind window: matches ending in new text are seen whole
+        let start = floor_boundary(text, self.emitted.saturating_sub(holdback));
+        let window = EvalRequest {
+            text: text[start..].to_string(),
+            ..self.req.clone()
+        };
+        let input = EvalInput::new(&window, &self.snap.normalization);
+        let mut out = evaluate_stage1(
+            &self.snap.window_rules,
+            &input,
+            &self.snap.evaluation_for(&window),
+        );
+
+        if matches!(out.action, Action::Block | Action::RequireReview) {
+            shift_evidence(&mut out, text, start);
+            return StreamStep::Blocked(Box::new(out));
+        }
+
+        if pii_should_run(&self.snap.pii, &window) {
+            let findings = detect_pii(&self.detector, &self.snap.pii, &window.text);
+            out.redact(findings.into_iter().map(|f| f.start..f.end));
+        }
+        let shift = |r: &Range<usize>| r.start + start..r.end + start;
+        let spans: Vec<Range<usize>> = out.redactions.iter().map(shift).collect();
+        let strips: Vec<Range<usize>> = out.strips.iter().map(shift).collect();
+
+        // hold back the tail, and any redaction crossing into it or ending
+        // at the end of the text (it may still grow)
+        let mut safe = if end {
+            text.len()
+        } else {
+            floor_boundary(text, text.len().saturating_sub(holdback))
+        };
+        if let Some(s) = spans
+            .iter()
+            .find(|s| s.start < safe && (s.end > safe || (!end && s.end == text.len())))
+        {
+            safe = s.start;
+        }
+        let from = self.emitted;
+        let safe = safe.max(from);
+
+        let local = |rs: &[Range<usize>]| -> Vec<Range<usize>> {
+            rs.iter()
+                .filter(|r| r.end > from && r.start < safe)
+                .map(|r| r.start.max(from) - from..r.end.min(safe) - from)
+                .collect()
+        };
+        let chunk = redact_spans(
+            &text[from..safe],
+            &local(&spans),
+            &local(&strips),
+            &self.snap.pii.redaction_token,
+        );
+        self.emitted = safe;
+        StreamStep::Emit(chunk)
+    }
+}
+
+/// Largest char boundary of `s` at or before `i`.
+fn floor_boundary(s: &str, mut i: usize) -> usize {
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+/// Moves window-relative text spans to offsets in the whole stream.
+fn shift_evidence(out: &mut Stage1Outcome, text: &str, start: usize) {
+    let chars = text[..start].chars().count();
+    for m in out.evidence.iter_mut().chain(out.matches.iter_mut()) {
+        if m.decoded.is_some() {
+            continue;
+        }
+        for s in &mut m.spans {
+            if matches!(s.field, Field::Text | Field::NormalizedText) {
+                s.start += start;
+                s.end += start;
+                s.char_start += chars;
+                s.char_end += chars;
+            }
+        }
+    }
+}
+
+// -----------------------------
+// /v1/eval/stream (WebSocket)
+// -----------------------------
+
+/// Client frames: one `start` (an eval request, usually `kind: response`,
+/// with `text` empty or holding the first chunk), any number of `chunk`s,
+/// then `end`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientFrame {
+    Start(Box<EvalRequest>),
+    Chunk { text: String },
+    End,
+}
+
+/// Server frames: redacted `chunk`s as they become safe, then either
+/// `blocked` (mid-stream) or `done` with the full-pipeline verdict on the
+/// complete text. `done` can still say `block` for matches longer than the
+/// look-behind window or rules decided on the whole text; the caller must
+/// then discard what it streamed.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerFrame {
+    Chunk { text: String },
+    Blocked(Box<EvalResponse>),
+    Done(Box<EvalResponse>),
+    Error { error: String },
+}
+
+pub async fn eval_stream(State(st): State<AppState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| run_stream(st, socket))
+}
+
+async fn run_stream(st: AppState, mut socket: WebSocket) {
+    let frame = run_frames(&st, &mut socket).await;
+    if let Some(frame) = frame {
+        let _ = send(&mut socket, &frame).await;
+    }
+    let _ = socket.send(WsMessage::Close(None)).await;
+}
+
+/// Drives one stream; returns the closing frame.
+async fn run_frames(st: &AppState, socket: &mut WebSocket) -> Option<ServerFrame> {
+    let mut req = match recv(socket).await? {
+        Ok(ClientFrame::Start(req)) => *req,
+        Ok(_) => return Some(error("expected a start frame")),
+        Err(e) => return Some(error(e)),
+    };
+    let request_id = *req.request_id.get_or_insert_with(uuid::Uuid::new_v4);
+    if !req.messages.is_empty() {
+        return Some(error("streams carry text, not messages"));
+    }
+
+    let snap = st.store.snapshot();
+    let first = std::mem::take(&mut req.text);
+    let mut ev = StreamEvaluator::new(snap, st.pii_regex.clone(), req);
+
+    let mut chunk = Some(first);
+    loop {
+        let step = match chunk.take() {
+            Some(text) => ev.push(&text),
+            None => match recv(socket).await? {
+                Ok(ClientFrame::Chunk { text }) => ev.push(&text),
+                Ok(ClientFrame::End) => break,
+                Ok(ClientFrame::Start(_)) => return Some(error("stream already started")),
+                Err(e) => return Some(error(e)),
+            },
+        };
+        match step {
+            Ok(StreamStep::Emit(text)) if text.is_empty() => {}
+            Ok(StreamStep::Emit(text)) => send(socket, &ServerFrame::Chunk { text }).await.ok()?,
+            Ok(StreamStep::Blocked(outcome)) => return Some(blocked(request_id, *outcome)),
+            Err(e) => return Some(error(e)),
+        }
+    }
+
+    match ev.finish() {
+        StreamStep::Emit(text) if text.is_empty() => {}
+        StreamStep::Emit(text) => send(socket, &ServerFrame::Chunk { text }).await.ok()?,
+        StreamStep::Blocked(outcome) => return Some(blocked(request_id, *outcome)),
+    }
+
+    // full pipeline on the complete text; its text was already streamed.
+    // `push` kept it within streaming.max_bytes, which replaces the
+    // (smaller) pii.max_bytes guard of a single eval
+    let (snap, req) = ev.into_request();
+    let max_bytes = snap.streaming.max_bytes;
+    Some(match evaluate_request_within(st, &snap, req, max_bytes) {
+        Ok(mut resp) => {
+            resp.output_text = None;
+            ServerFrame::Done(Box::new(resp))
+        }
+        Err((_, msg)) => error(msg),
+    })
+}
+
+/// Next client frame; `None` once the socket is closed.
+async fn recv(socket: &mut WebSocket) -> Option<Result<ClientFrame, String>> {
+    loop {
+        match socket.recv().await? {
+            Ok(WsMessage::Text(t)) => {
+                return Some(serde_json::from_str(&t).map_err(|e| format!("invalid frame: {e}")))
+            }
+            Ok(WsMessage::Close(_)) | Err(_) => return None,
+            // pings are answered by axum; binary frames are ignored
+            Ok(_) => {}
+        }
+    }
+}
+
+async fn send(socket: &mut WebSocket, frame: &ServerFrame) -> Result<(), axum::Error> {
+    let json = serde_json::to_string(frame).unwrap_or_default();
+    socket.send(WsMessage::Text(json)).await
+}
+
+fn blocked(request_id: uuid::Uuid, outcome: Stage1Outcome) -> ServerFrame {
+    ServerFrame::Blocked(Box::new(eval_response(request_id, outcome, None, None)))
+}
+
+fn error(e: impl ToString) -> ServerFrame {
+    ServerFrame::Error {
+        error: e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode::PayloadDecoder, store::RuleStore};
+    use futures_util::{SinkExt, StreamExt};
+    use tempfile::TempDir;
+    use tokio_tungstenite::tungstenite::Message as Frame;
+
+    const POLICY: &str = r#"
+rules:
+  - id: no-leaks
+    applies_to: response
+    action: block
+    priority: 1
+    when:
+      any:
+        - {type: keywords, field: text, values: [internal only]}
+  - id: mask-codename
+    applies_to: response
+    action: redact
+    priority: 2
+    when:
+      any:
+        - {type: keywords, field: text, values: [project falcon]}
+  - id: unexplained-password
+    applies_to: response
+    action: block
+    priority: 3
+    when:
+      all:
+        - {type: keywords, field: text, values: [password]}
+      not: {type: keywords, field: text, values: [example]}
+pii:
+  enabled: true
+  applies_to: both
+  mode: redact
+  redaction_token: "[X]"
+  detectors: {email: true}
+  max_bytes: 32768
+  include_findings: false
+streaming:
+  holdback_bytes: 16
+"#;
+
+    async fn store(dir: &TempDir) -> RuleStore {
+        let path = dir.path().join("policy.yaml");
+        tokio::fs::write(&path, POLICY).await.unwrap();
+        RuleStore::load(path).await.unwrap()
+    }
+
+    async fn evaluator(dir: &TempDir) -> StreamEvaluator {
+        let req: EvalRequest = serde_json::from_str(r#"{"kind": "response"}"#).unwrap();
+        let snap = store(dir).await.snapshot();
+        StreamEvaluator::new(snap, PiiRegexDetector::new().unwrap(), req)
+    }
+
+    fn run(ev: &mut StreamEvaluator, chunks: &[&str]) -> String {
+        let mut out = String::new();
+        for c in chunks {
+            match ev.push(c).unwrap() {
+                StreamStep::Emit(t) => out.push_str(&t),
+                StreamStep::Blocked(o) => panic!("blocked by {:?}", o.matched_rule),
+            }
+        }
+        match ev.finish() {
+            StreamStep::Emit(t) => out.push_str(&t),
+            StreamStep::Blocked(o) => panic!("blocked by {:?}", o.matched_rule),
+        }
+        out
+    }
+
+    #[tokio::test]
+    async fn redacts_matches_split_across_chunks() {
+        let dir = TempDir::new().unwrap();
+        let mut ev = evaluator(&dir).await;
+        let out = run(
+            &mut ev,
+            &[
+                "Mail bob@exa",
+                "mple.com about proj",
+                "ect fal",
+                "con today, thanks",
+            ],
+        );
+        assert_eq!(out, "Mail [X] about [X] today, thanks");
+    }
+
+    #[tokio::test]
+    async fn emits_before_the_end_and_holds_back_the_tail() {
+        let dir = TempDir::new().unwrap();
+        let mut ev = evaluator(&dir).await;
+        let StreamStep::Emit(first) = ev
+            .push("The quick brown fox jumps over the lazy dog")
+            .unwrap()
+        else {
+            panic!("blocked")
+        };
+        // everything but the last 16 bytes
+        assert_eq!(first, "The quick brown fox jumps o");
+        let StreamStep::Emit(rest) = ev.finish() else {
+            panic!("blocked")
+        };
+        assert_eq!(rest, "ver the lazy dog");
+    }
+
+    #[tokio::test]
+    async fn blocks_mid_stream_with_stream_offsets() {
+        let dir = TempDir::new().unwrap();
+        let mut ev = evaluator(&dir).await;
+        let filler = "x".repeat(40);
+        assert!(matches!(ev.push(&filler).unwrap(), StreamStep::Emit(_)));
+        assert!(matches!(
+            ev.push(" this is inte").unwrap(),
+            StreamStep::Emit(_)
+        ));
+        let StreamStep::Blocked(out) = ev.push("rnal only").unwrap() else {
+            panic!("not blocked")
+        };
+        assert_eq!(out.matched_rule.as_deref(), Some("no-leaks"));
+        let span = &out.evidence[0].spans[0];
+        assert_eq!(&ev.req.text[span.start..span.end], "internal only");
+    }
+
+    #[tokio::test]
+    async fn rejects_streams_over_max_bytes() {
+        let dir = TempDir::new().unwrap();
+        let mut ev = evaluator(&dir).await;
+        Arc::make_mut(&mut ev.snap).streaming.max_bytes = 8;
+        assert!(ev.push("12345").is_ok());
+        assert!(ev.push("6789").is_err());
+    }
+
+    #[tokio::test]
+    async fn whole_text_rules_wait_for_the_end() {
+        let dir = TempDir::new().unwrap();
+        let mut ev = evaluator(&dir).await;
+        assert_eq!(ev.snap.compiled.len(), 3);
+        assert_eq!(ev.snap.window_rules.len(), 2);
+
+        // `example` has left the look-behind window when `password` arrives
+        let filler = "x".repeat(40);
+        let out = run(&mut ev, &["Example: ", &filler, " the password is hunter2"]);
+        assert!(out.ends_with("the password is hunter2"));
+    }
+
+    /// Streams `frames` through `/v1/eval/stream`; returns the emitted text
+    /// and the closing frame.
+    async fn stream(dir: &TempDir, frames: &[String]) -> (String, serde_json::Value) {
+        let state = AppState {
+            store: store(dir).await,
+            pii_regex: PiiRegexDetector::new().unwrap(),
+            decoder: PayloadDecoder::new().unwrap(),
+            upstream: None,
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, crate::api::router(state))
+                .await
+                .unwrap()
+        });
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/v1/eval/stream"))
+            .await
+            .unwrap();
+        for frame in frames {
+            ws.send(Frame::text(frame.as_str())).await.unwrap();
+        }
+
+        let mut text = String::new();
+        let done = loop {
+            let Some(Ok(Frame::Text(t))) = ws.next().await else {
+                panic!("stream closed early")
+            };
+            let v: serde_json::Value = serde_json::from_str(&t).unwrap();
+            match v["type"].as_str().unwrap() {
+                "chunk" => text.push_str(v["text"].as_str().unwrap()),
+                _ => break v,
+            }
+        };
+        (text, done)
+    }
+
+    #[tokio::test]
+    async fn websocket_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let frames = [
+            r#"{"type": "start", "kind": "response", "text": "Hello, write to "}"#,
+            r#"{"type": "chunk", "text": "ann@example.org please"}"#,
+            r#"{"type": "end"}"#,
+        ];
+        let (text, done) = stream(&dir, &frames.map(String::from)).await;
+        assert_eq!(text, "Hello, write to [X] please");
+        assert_eq!(done["type"], "done");
+        assert_eq!(done["action"], "allow");
+    }
+
+    #[tokio::test]
+    async fn streams_over_pii_max_bytes_get_a_verdict() {
+        let dir = TempDir::new().unwrap();
+        let chunk = serde_json::json!({"type": "chunk", "text": "lorem ipsum ".repeat(1000)});
+        let mut frames = vec![r#"{"type": "start", "kind": "response"}"#.to_string()];
+        // 48 KB, over `pii.max_bytes` (32 KiB) and under `streaming.max_bytes`
+        frames.extend(std::iter::repeat_n(chunk.to_string(), 4));
+        frames.push(r#"{"type": "end"}"#.to_string());
+
+        let (text, done) = stream(&dir, &frames).await;
+        assert_eq!(text.len(), 48_000);
+        assert_eq!(done["type"], "done", "{done}");
+        assert_eq!(done["action"], "allow");
+    }
+}
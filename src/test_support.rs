@@ -0,0 +1,12 @@
+//! Fixtures shared by unit tests in several modules.
+
+use uuid::Uuid;
+
+use crate::policy::BatchResult;
+
+/// A `BatchResult` written as JSON, with a nil `request_id`.
+pub fn batch_result(json: &str) -> BatchResult {
+    let mut v: serde_json::Value = serde_json::from_str(json).unwrap();
+    v["request_id"] = serde_json::json!(Uuid::nil());
+    serde_json::from_value(v).unwrap()
+}
@@ -0,0 +1,627 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_yaml::Value;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+
+use crate::compile::compile_match;
+use crate::policy::{
+    Action, AppliesTo, Condition, DecodingConfig, EvaluationConfig, NormalizationConfig, PiiConfig,
+    PolicyFile, ResponseSchema, Rule, SemanticConfig, StreamingConfig, When,
+};
+use crate::response_schema::compile_response_schemas;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyErrorKind {
+    /// Not well-formed YAML.
+    Syntax,
+    /// Wrong shape: unknown or missing fields, wrong types or values.
+    Schema,
+    /// Well-formed but rejected when compiling (regex, JSONPath, JSON Schema).
+    Compile,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyError {
+    pub kind: PolicyErrorKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule_id: Option<String>,
+    /// JSON Pointer into the policy, e.g. `/rules/3/when/any/0`.
+    pub path: String,
+    /// 1-based position in the submitted YAML of `path` (or the nearest
+    /// enclosing node that exists).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl PolicyError {
+    /// An error serde_yaml reported for the whole document, at its position.
+    pub fn yaml(kind: PolicyErrorKind, e: &serde_yaml::Error) -> Self {
+        let loc = e.location();
+        Self {
+            kind,
+            rule_id: None,
+            path: String::new(),
+            line: loc.as_ref().map(|l| l.line()),
+            column: loc.as_ref().map(|l| l.column()),
+            message: e.to_string(),
+        }
+    }
+}
+
+/// Printed like a lint issue:
+/// error[compile] rule `a` at /rules/0/when/not (line 7, column 17): ...
+impl std::fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            PolicyErrorKind::Syntax => "syntax",
+            PolicyErrorKind::Schema => "schema",
+            PolicyErrorKind::Compile => "compile",
+        };
+        write!(f, "error[{kind}]")?;
+        if let Some(id) = &self.rule_id {
+            write!(f, " rule `{id}`")?;
+        }
+        if !self.path.is_empty() {
+            write!(f, " at {}", self.path)?;
+        }
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, " (line {line}, column {column})")?,
+            (Some(line), None) => write!(f, " (line {line})")?,
+            _ => {}
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Body of a 400 from the policy admin endpoints.
+#[derive(Debug, Serialize)]
+pub struct PolicyErrors {
+    pub errors: Vec<PolicyError>,
+}
+
+/// Parses and compiles a policy document, reporting every problem found
+/// rather than only the first.
+pub fn parse_policy(src: &str) -> Result<PolicyFile, Vec<PolicyError>> {
+    let doc: Value = match serde_yaml::from_str(src) {
+        Ok(doc) => doc,
+        Err(e) => return Err(vec![PolicyError::yaml(PolicyErrorKind::Syntax, &e)]),
+    };
+
+    let mut cx = Checker {
+        locations: locations(src),
+        rule_id: None,
+        errors: vec![],
+    };
+    let parsed = serde_yaml::from_value::<PolicyFile>(doc.clone());
+    match &parsed {
+        Ok(policy) => cx.compile_policy(policy),
+        Err(e) => {
+            cx.check_document(&doc);
+            if cx.errors.is_empty() {
+                cx.push(PolicyErrorKind::Schema, "", e.to_string());
+            }
+        }
+    }
+
+    match parsed {
+        Ok(policy) if cx.errors.is_empty() => Ok(policy),
+        _ => Err(cx.errors),
+    }
+}
+
+/// Compile errors of a policy that is already deserialized (edited through
+/// the API, or embedded in a dry-run request). Without a source document
+/// there are no line/column positions.
+pub fn check_policy(policy: &PolicyFile) -> Vec<PolicyError> {
+    let mut cx = Checker {
+        locations: HashMap::new(),
+        rule_id: None,
+        errors: vec![],
+    };
+    cx.compile_policy(policy);
+    cx.errors
+}
+
+struct Checker {
+    locations: HashMap<String, Marker>,
+    rule_id: Option<String>,
+    errors: Vec<PolicyError>,
+}
+
+impl Checker {
+    fn push(&mut self, kind: PolicyErrorKind, path: &str, message: String) {
+        // nearest enclosing node that is in the document
+        let mut at = path;
+        let marker = loop {
+            if let Some(m) = self.locations.get(at) {
+                break Some(m);
+            }
+            match at.rfind('/') {
+                Some(i) => at = &at[..i],
+                None => break None,
+            }
+        };
+        self.errors.push(PolicyError {
+            kind,
+            rule_id: self.rule_id.clone(),
+            path: path.to_string(),
+            line: marker.map(|m| m.line()),
+            column: marker.map(|m| m.col() + 1),
+            message,
+        });
+    }
+
+    /// Pushes a schema error at `path` unless `v` is a valid `T`.
+    fn expect<T: DeserializeOwned>(&mut self, v: &Value, path: &str) -> Option<T> {
+        match serde_yaml::from_value(v.clone()) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                self.push(PolicyErrorKind::Schema, path, e.to_string());
+                None
+            }
+        }
+    }
+
+    fn required(&mut self, map: &serde_yaml::Mapping, fields: &[&str], path: &str) {
+        for f in fields {
+            if !map.contains_key(*f) {
+                self.push(
+                    PolicyErrorKind::Schema,
+                    path,
+                    format!("missing field `{f}`"),
+                );
+            }
+        }
+    }
+
+    // -----------------------------
+    // Schema (when the document does not deserialize)
+    // -----------------------------
+
+    fn check_document(&mut self, doc: &Value) {
+        let Some(map) = doc.as_mapping() else {
+            self.push(
+                PolicyErrorKind::Schema,
+                "",
+                "policy must be a mapping".into(),
+            );
+            return;
+        };
+        self.required(map, &["rules"], "");
+
+        if let Some(rules) = map.get("rules") {
+            match rules.as_sequence() {
+                Some(rules) => {
+                    for (i, r) in rules.iter().enumerate() {
+                        self.check_rule(r, &format!("/rules/{i}"));
+                    }
+                    self.rule_id = None;
+                }
+                None => self.push(
+                    PolicyErrorKind::Schema,
+                    "/rules",
+                    "expected a sequence".into(),
+                ),
+            }
+        }
+
+        let section = |k: &str| map.get(k).map(|v| (v, format!("/{k}")));
+        if let Some((v, p)) = section("pii") {
+            self.expect::<PiiConfig>(v, &p);
+        }
+        if let Some((v, p)) = section("semantic") {
+            self.expect::<SemanticConfig>(v, &p);
+        }
+        if let Some((v, p)) = section("evaluation") {
+            self.expect::<EvaluationConfig>(v, &p);
+        }
+        if let Some((v, p)) = section("normalization") {
+            self.expect::<NormalizationConfig>(v, &p);
+        }
+        if let Some((v, p)) = section("decoding") {
+            self.expect::<DecodingConfig>(v, &p);
+        }
+        if let Some((v, p)) = section("streaming") {
+            self.expect::<StreamingConfig>(v, &p);
+        }
+        if let Some((v, p)) = section("response_schemas") {
+            if let Some(schemas) = self.expect::<Vec<Value>>(v, &p) {
+                let parsed: Vec<Option<ResponseSchema>> = schemas
+                    .iter()
+                    .enumerate()
+                    .map(|(i, s)| self.expect(s, &format!("{p}/{i}")))
+                    .collect();
+                if let Some(all) = parsed.into_iter().collect::<Option<Vec<_>>>() {
+                    self.compile_schemas(&all);
+                }
+            }
+        }
+    }
+
+    fn check_rule(&mut self, v: &Value, path: &str) {
+        self.rule_id = v.get("id").and_then(Value::as_str).map(str::to_string);
+        if let Ok(rule) = serde_yaml::from_value::<Rule>(v.clone()) {
+            self.compile_when(&rule.when, &format!("{path}/when"));
+            return;
+        }
+        let Some(map) = v.as_mapping() else {
+            self.push(
+                PolicyErrorKind::Schema,
+                path,
+                "rule must be a mapping".into(),
+            );
+            return;
+        };
+        self.required(
+            map,
+            &["id", "applies_to", "action", "priority", "when"],
+            path,
+        );
+
+        for (k, v) in map {
+            let key = k.as_str().unwrap_or_default();
+            let p = format!("{path}/{}", escape(key));
+            match key {
+                "id" => drop(self.expect::<String>(v, &p)),
+                "description" | "message" => drop(self.expect::<Option<String>>(v, &p)),
+                "applies_to" => drop(self.expect::<AppliesTo>(v, &p)),
+                "action" => drop(self.expect::<Action>(v, &p)),
+                "priority" => drop(self.expect::<u32>(v, &p)),
+                "enabled" => drop(self.expect::<bool>(v, &p)),
+                "when" => self.check_when(v, &p),
+                // unknown rule fields are ignored, like serde does
+                _ => {}
+            }
+        }
+    }
+
+    fn check_when(&mut self, v: &Value, path: &str) {
+        if let Ok(when) = serde_yaml::from_value::<When>(v.clone()) {
+            self.compile_when(&when, path);
+            return;
+        }
+        let Some(map) = v.as_mapping() else {
+            self.push(
+                PolicyErrorKind::Schema,
+                path,
+                "condition must be a mapping".into(),
+            );
+            return;
+        };
+        for (k, v) in map {
+            let key = k.as_str().unwrap_or_default();
+            let p = format!("{path}/{}", escape(key));
+            match key {
+                "any" | "all" => match v.as_sequence() {
+                    Some(items) => {
+                        for (i, c) in items.iter().enumerate() {
+                            self.check_condition(c, &format!("{p}/{i}"));
+                        }
+                    }
+                    None => self.push(PolicyErrorKind::Schema, &p, "expected a sequence".into()),
+                },
+                "not" => self.check_condition(v, &p),
+                _ => self.push(
+                    PolicyErrorKind::Schema,
+                    &p,
+                    format!("unknown field `{key}`, expected one of `any`, `all`, `not`"),
+                ),
+            }
+        }
+    }
+
+    /// A match expression (has `type`) or a nested group.
+    fn check_condition(&mut self, v: &Value, path: &str) {
+        if v.get("type").is_some() {
+            if let Some(expr) = self.expect(v, path) {
+                self.compile_condition(&Condition::Match(expr), path);
+            }
+        } else {
+            self.check_when(v, path);
+        }
+    }
+
+    // -----------------------------
+    // Compile (well-formed input)
+    // -----------------------------
+
+    fn compile_policy(&mut self, policy: &PolicyFile) {
+        for (i, rule) in policy.rules.iter().enumerate() {
+            self.rule_id = Some(rule.id.clone());
+            self.compile_when(&rule.when, &format!("/rules/{i}/when"));
+        }
+        self.rule_id = None;
+        self.compile_schemas(&policy.response_schemas);
+    }
+
+    fn compile_when(&mut self, when: &When, path: &str) {
+        for (i, c) in when.any.iter().enumerate() {
+            self.compile_condition(c, &format!("{path}/any/{i}"));
+        }
+        for (i, c) in when.all.iter().enumerate() {
+            self.compile_condition(c, &format!("{path}/all/{i}"));
+        }
+        if let Some(c) = &when.not {
+            self.compile_condition(c, &format!("{path}/not"));
+        }
+    }
+
+    fn compile_condition(&mut self, cond: &Condition, path: &str) {
+        match cond {
+            Condition::Match(expr) => {
+                if let Err(e) = compile_match(expr) {
+                    self.push(PolicyErrorKind::Compile, path, format!("{e:#}"));
+                }
+            }
+            Condition::Group(when) => self.compile_when(when, path),
+        }
+    }
+
+    fn compile_schemas(&mut self, schemas: &[ResponseSchema]) {
+        let mut seen = HashSet::new();
+        for (i, s) in schemas.iter().enumerate() {
+            let path = format!("/response_schemas/{i}");
+            if !seen.insert(s.id.as_str()) {
+                let msg = format!("duplicate response schema id: {}", s.id);
+                self.push(PolicyErrorKind::Schema, &format!("{path}/id"), msg);
+            } else if let Err(e) = compile_response_schemas(std::slice::from_ref(s)) {
+                self.push(PolicyErrorKind::Compile, &path, format!("{e:#}"));
+            }
+        }
+    }
+}
+
+/// JSON Pointer reference token.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+// -----------------------------
+// Source positions
+// -----------------------------
+
+/// Position of every node of the (first) YAML document, by JSON Pointer.
+fn locations(src: &str) -> HashMap<String, Marker> {
+    let mut index = Index::default();
+    // positions are best effort; serde_yaml already accepted the document
+    let _ = Parser::new_from_str(src).load(&mut index, false);
+    index.locations
+}
+
+#[derive(Default)]
+struct Index {
+    locations: HashMap<String, Marker>,
+    stack: Vec<Frame>,
+}
+
+enum Frame {
+    Map {
+        path: String,
+        key: Option<String>,
+    },
+    Seq {
+        path: String,
+        next: usize,
+    },
+    /// Inside a complex (non-scalar) mapping key.
+    Skip,
+}
+
+impl MarkedEventReceiver for Index {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        let starts = matches!(ev, Event::MappingStart(..) | Event::SequenceStart(..));
+        match ev {
+            Event::Scalar(..)
+            | Event::Alias(_)
+            | Event::MappingStart(..)
+            | Event::SequenceStart(..) => {
+                let path = match self.stack.last_mut() {
+                    None => Some(String::new()),
+                    Some(Frame::Skip) => None,
+                    Some(Frame::Map {
+                        key: key @ None, ..
+                    }) => {
+                        // a mapping key, not a node of its own
+                        *key = Some(match &ev {
+                            Event::Scalar(s, ..) => escape(s),
+                            _ => "?".to_string(),
+                        });
+                        if starts {
+                            self.stack.push(Frame::Skip);
+                        }
+                        return;
+                    }
+                    Some(Frame::Map { path, key }) => {
+                        Some(format!("{path}/{}", key.take().unwrap_or_default()))
+                    }
+                    Some(Frame::Seq { path, next }) => {
+                        *next += 1;
+                        Some(format!("{path}/{}", *next - 1))
+                    }
+                };
+                if let Some(p) = &path {
+                    self.locations.entry(p.clone()).or_insert(mark);
+                }
+                match (ev, path) {
+                    (Event::MappingStart(..), Some(path)) => {
+                        self.stack.push(Frame::Map { path, key: None })
+                    }
+                    (Event::SequenceStart(..), Some(path)) => {
+                        self.stack.push(Frame::Seq { path, next: 0 })
+                    }
+                    (Event::MappingStart(..) | Event::SequenceStart(..), None) => {
+                        self.stack.push(Frame::Skip)
+                    }
+                    _ => {}
+                }
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(src: &str) -> Vec<PolicyError> {
+        parse_policy(src).unwrap_err()
+    }
+
+    #[test]
+    fn valid_policy_parses() {
+        let policy = parse_policy(
+            r#"
+rules:
+  - id: a
+    applies_to: prompt
+    action: block
+    priority: 1
+    when: {any: [{type: regex, field: text, pattern: 'x+'}]}
+"#,
+        )
+        .unwrap();
+        assert_eq!(policy.rules[0].id, "a");
+    }
+
+    #[test]
+    fn syntax_error_has_location() {
+        let e = errors("rules:\n  - id: a\n   bad: [\n");
+        assert_eq!(e.len(), 1);
+        assert_eq!(e[0].kind, PolicyErrorKind::Syntax);
+        assert!(e[0].line.is_some());
+    }
+
+    #[test]
+    fn collects_every_error_with_rule_id_and_position() {
+        let src = r#"rules:
+  - id: ok
+    applies_to: prompt
+    action: block
+    priority: 1
+    when: {any: [{type: keywords, field: text, values: [x]}]}
+  - id: bad-regex
+    applies_to: prompt
+    action: block
+    priority: 2
+    when:
+      any:
+        - type: keywords
+          field: text
+          values: [y]
+        - type: regex
+          field: text
+          pattern: '(unclosed'
+  - id: bad-shape
+    applies_to: everywhere
+    action: block
+    when:
+      all:
+        - type: exact
+          field: nowhere
+          value: z
+semantic:
+  threshold: high
+"#;
+        let e = errors(src);
+        let summary: Vec<(PolicyErrorKind, Option<&str>, &str, Option<usize>)> = e
+            .iter()
+            .map(|e| (e.kind, e.rule_id.as_deref(), e.path.as_str(), e.line))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    PolicyErrorKind::Compile,
+                    Some("bad-regex"),
+                    "/rules/1/when/any/1",
+                    Some(16)
+                ),
+                (
+                    PolicyErrorKind::Schema,
+                    Some("bad-shape"),
+                    "/rules/2",
+                    Some(19)
+                ),
+                (
+                    PolicyErrorKind::Schema,
+                    Some("bad-shape"),
+                    "/rules/2/applies_to",
+                    Some(20)
+                ),
+                (
+                    PolicyErrorKind::Schema,
+                    Some("bad-shape"),
+                    "/rules/2/when/all/0",
+                    Some(24)
+                ),
+                (PolicyErrorKind::Schema, None, "/semantic", Some(28)),
+            ]
+        );
+        assert!(e[1].message.contains("priority"));
+        assert!(e[3].message.contains("nowhere"));
+        assert_eq!(e[2].column, Some(17));
+    }
+
+    #[test]
+    fn compile_errors_in_a_well_formed_policy() {
+        let e = errors(
+            r#"
+rules:
+  - id: a
+    applies_to: prompt
+    action: block
+    priority: 1
+    when: {not: {type: regex, field: text, pattern: '['}}
+response_schemas:
+  - {id: s, schema: {type: object}}
+  - {id: s, schema: {type: object}}
+"#,
+        );
+        let paths: Vec<&str> = e.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["/rules/0/when/not", "/response_schemas/1/id"]);
+        assert_eq!(e[0].rule_id.as_deref(), Some("a"));
+        assert_eq!((e[0].line, e[0].column), (Some(7), Some(17)));
+        assert!(e[0]
+            .to_string()
+            .starts_with("error[compile] rule `a` at /rules/0/when/not (line 7, column 17): "));
+    }
+
+    #[test]
+    fn checks_a_parsed_policy_without_positions() {
+        let mut policy = parse_policy(
+            r#"
+rules:
+  - id: a
+    applies_to: prompt
+    action: block
+    priority: 1
+    when: {any: [{type: regex, field: text, pattern: 'x+'}]}
+"#,
+        )
+        .unwrap();
+        assert!(check_policy(&policy).is_empty());
+
+        let mut added = policy.rules[0].clone();
+        added.id = "b".to_string();
+        added.when =
+            serde_yaml::from_str("{all: [{type: regex, field: text, pattern: '(x'}]}").unwrap();
+        policy.rules.push(added);
+
+        let e = check_policy(&policy);
+        assert_eq!(e.len(), 1);
+        assert_eq!(e[0].kind, PolicyErrorKind::Compile);
+        assert_eq!(e[0].rule_id.as_deref(), Some("b"));
+        assert_eq!(e[0].path, "/rules/1/when/all/0");
+        assert_eq!((e[0].line, e[0].column), (None, None));
+    }
+}